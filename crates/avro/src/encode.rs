@@ -118,6 +118,14 @@ fn maybe_encode<'s, 'n, N: AsNode>(
             Ok(true)
         }
 
+        (Schema::Enum(schema), Node::String(v)) => {
+            let Some(index) = schema.symbols.iter().position(|symbol| symbol == v) else {
+                return Ok(false);
+            };
+            zig_zag(b, index as i64);
+            Ok(true)
+        }
+
         (Schema::String, Node::String(v)) => {
             zig_zag(b, v.len() as i64);
             b.extend(v.as_bytes());
@@ -177,6 +185,19 @@ fn maybe_encode<'s, 'n, N: AsNode>(
             zig_zag(b, dt as i64);
             Ok(true)
         }
+        (Schema::TimestampMillis, Node::String(s)) => {
+            let Ok(dt) =
+                time::OffsetDateTime::parse(s, &time::format_description::well_known::Rfc3339)
+            else {
+                return Ok(false);
+            };
+            let dt = dt.unix_timestamp_nanos() / 1_000_000; // Nanos to milliseconds.
+            zig_zag(b, dt as i64);
+            Ok(true)
+        }
+        (Schema::Decimal(schema), Node::String(s)) => Ok(encode_decimal(b, schema, s)),
+        (Schema::Decimal(schema), Node::NegInt(v)) => Ok(encode_decimal(b, schema, &v.to_string())),
+        (Schema::Decimal(schema), Node::PosInt(v)) => Ok(encode_decimal(b, schema, &v.to_string())),
         (Schema::Uuid, Node::String(s)) => {
             zig_zag(b, s.len() as i64);
             b.extend(s.as_bytes());
@@ -311,6 +332,73 @@ fn zig_zag(b: &mut Vec<u8>, z: i64) {
     }
 }
 
+// Encode decimal string `s` as the unscaled, two's-complement integer of `schema`.
+// Returns false if `s` isn't representable by the schema.
+fn encode_decimal(b: &mut Vec<u8>, schema: &apache_avro::schema::DecimalSchema, s: &str) -> bool {
+    let Some(unscaled) = parse_decimal(s, schema.precision, schema.scale) else {
+        return false;
+    };
+    let v = decimal_bytes(unscaled);
+
+    match schema.inner.as_ref() {
+        Schema::Bytes => {
+            zig_zag(b, v.len() as i64);
+            b.extend(v);
+        }
+        Schema::Fixed(fixed) if v.len() <= fixed.size => {
+            // Sign-extend to the fixed size.
+            let fill = if unscaled < 0 { 0xff } else { 0x00 };
+            b.extend(std::iter::repeat(fill).take(fixed.size - v.len()));
+            b.extend(v);
+        }
+        _ => return false,
+    }
+    true
+}
+
+// Parse a decimal string, such as "-12.34", into its unscaled integer having
+// `scale` fractional digits. Returns None if `s` isn't a plain decimal,
+// has more fractional digits than `scale`, or more digits than `precision`.
+fn parse_decimal(s: &str, precision: usize, scale: usize) -> Option<i128> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+
+    if (int.is_empty() && frac.is_empty())
+        || frac.len() > scale
+        || !int.bytes().chain(frac.bytes()).all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let padding = std::iter::repeat(b'0').take(scale - frac.len());
+
+    let mut unscaled: i128 = 0;
+    for c in int.bytes().chain(frac.bytes()).chain(padding) {
+        unscaled = unscaled.checked_mul(10)?.checked_add((c - b'0') as i128)?;
+    }
+    if unscaled.to_string().len() > precision {
+        return None;
+    }
+    Some(if negative { -unscaled } else { unscaled })
+}
+
+// Minimal big-endian two's-complement representation of `v`.
+fn decimal_bytes(v: i128) -> Vec<u8> {
+    let bytes = v.to_be_bytes();
+
+    // Strip leading bytes which only repeat the sign of the byte after them.
+    let mut start = 0;
+    while start < bytes.len() - 1
+        && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
 lazy_static::lazy_static! {
     // The set of allowed characters in an AVRO field name.
     static ref ISO8601_DURATION : regex::Regex = regex::Regex::new(r"P(?:(\d+)Y)?(?:(\d+)M)?(?:(\d+)D)?T?(?:(\d+)H)?(?:(\d+)M)?(?:(\d+(?:\.\d+)?)S)?").unwrap();
@@ -319,6 +407,7 @@ lazy_static::lazy_static! {
 
 #[cfg(test)]
 mod test {
+    use super::Schema;
     use serde_json::json;
 
     #[test]
@@ -409,6 +498,66 @@ mod test {
         insta::assert_debug_snapshot!(recovered);
     }
 
+    #[test]
+    fn test_logical_types() {
+        use apache_avro::{types::Value, Decimal};
+
+        let schema = Schema::parse(&json!({
+            "type": "record",
+            "name": "root",
+            "fields": [
+                {"name": "amount", "type": {"type": "bytes", "logicalType": "decimal", "precision": 6, "scale": 2}},
+                {"name": "at", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                {"name": "day", "type": {"type": "int", "logicalType": "date"}},
+                {"name": "debt", "type": {"type": "fixed", "name": "debt", "size": 4, "logicalType": "decimal", "precision": 8, "scale": 3}},
+            ],
+        }))
+        .unwrap();
+
+        let instance = json!({
+            "amount": "12.34",
+            "at": "2024-01-02T03:04:05.678Z",
+            "day": "2024-01-02",
+            "debt": -5,
+        });
+
+        let mut b = Vec::new();
+        super::encode(json::Location::Root, &mut b, &schema, &instance).unwrap();
+
+        let recovered = apache_avro::from_avro_datum(&schema, &mut &b[..], None).unwrap();
+        assert_eq!(
+            recovered,
+            Value::Record(vec![
+                (
+                    "amount".to_string(),
+                    Value::Decimal(Decimal::from(vec![0x04, 0xd2]))
+                ),
+                ("at".to_string(), Value::TimestampMillis(1704164645678)),
+                ("day".to_string(), Value::Date(19724)),
+                (
+                    "debt".to_string(),
+                    Value::Decimal(Decimal::from(vec![0xff, 0xff, 0xec, 0x78]))
+                ),
+            ])
+        );
+
+        // Values which aren't representable by their logical type are errors.
+        for (field, value) in [
+            ("amount", json!("1.234")),   // Too many fractional digits.
+            ("amount", json!("12345.6")), // Exceeds precision.
+            ("amount", json!("1e3")),
+            ("at", json!("2024-01-02")),
+            ("day", json!("2024-13-01")),
+        ] {
+            let mut instance = instance.clone();
+            instance[field] = value;
+
+            let err = super::encode(json::Location::Root, &mut Vec::new(), &schema, &instance)
+                .unwrap_err();
+            assert!(err.to_string().contains(&format!("/{field}")), "{err}");
+        }
+    }
+
     fn to_hex(v: &[u8]) -> String {
         hexdump::hexdump_iter(v)
             .map(|line| format!(" {line}"))
@@ -3,7 +3,7 @@ mod schema;
 
 // Re-export fundamental schema types so crates don't have to depend on apache_avro.
 pub use apache_avro::{
-    schema::{Name as RecordName, RecordField, RecordSchema, UnionSchema},
+    schema::{Name as RecordName, RecordField, RecordFieldOrder, RecordSchema, UnionSchema},
     Schema,
 };
 
@@ -40,20 +40,105 @@ pub enum Error {
     KeyComponentsMismatch { expected: usize, actual: usize },
     #[error("failed to parse string {0:?} into double")]
     ParseFloat(String, #[source] std::num::ParseFloatError),
+    #[error("failed to resolve datum from its writer schema into the reader schema")]
+    Resolve(#[source] apache_avro::Error),
+    #[error("projection field {0:?} is not a valid AVRO field name")]
+    ProjectionName(String),
+    #[error("projection field {0:?} is declared by more than one projection, or is reserved")]
+    ProjectionConflict(String),
 }
 
 /// Map a JSON schema bundle and key pointers into its equivalent AVRO schema.
 pub fn json_schema_to_avro(
     json_schema: &str,
     key: &[doc::Pointer],
+) -> Result<(apache_avro::Schema, apache_avro::Schema), Error> {
+    json_schema_to_avro_with_max_depth(json_schema, key, None)
+}
+
+/// Map a JSON schema bundle and key pointers into its equivalent AVRO schema,
+/// as does `json_schema_to_avro`. If `max_nesting_depth` is set, the value schema
+/// maps objects and arrays nested beyond that depth (where the document root is
+/// at depth one) into string fields holding their JSON encoding.
+pub fn json_schema_to_avro_with_max_depth(
+    json_schema: &str,
+    key: &[doc::Pointer],
+    max_nesting_depth: Option<usize>,
+) -> Result<(apache_avro::Schema, apache_avro::Schema), Error> {
+    json_schema_to_avro_with_options(
+        json_schema,
+        key,
+        &SchemaOptions {
+            max_nesting_depth,
+            ..Default::default()
+        },
+    )
+}
+
+/// SchemaOptions customize the mapping of a JSON schema into an AVRO value schema.
+#[derive(Clone, Debug, Default)]
+pub struct SchemaOptions {
+    /// If set, objects and arrays nested beyond this depth are mapped into
+    /// string fields holding their JSON encoding.
+    pub max_nesting_depth: Option<usize>,
+    /// If true, string locations having an `enum` of values which are all valid
+    /// AVRO enum symbols are mapped into AVRO enums. Locations having other
+    /// enum values continue to map into strings.
+    pub enums: bool,
+}
+
+/// Map a JSON schema bundle and key pointers into its equivalent AVRO schema,
+/// as customized by `options`. Key schemas are unaffected by `options`.
+pub fn json_schema_to_avro_with_options(
+    json_schema: &str,
+    key: &[doc::Pointer],
+    options: &SchemaOptions,
 ) -> Result<(apache_avro::Schema, apache_avro::Schema), Error> {
     let json_schema = doc::validation::build_bundle(json_schema)?;
     let validator = doc::Validator::new(json_schema)?;
     let shape = doc::Shape::infer(&validator.schemas()[0], validator.schema_index());
+    let max_depth = options.max_nesting_depth.unwrap_or(usize::MAX);
 
     Ok((
         schema::key_to_avro(key, shape.clone()),
-        schema::shape_to_avro(json::Location::Root, shape, true),
+        schema::shape_to_avro(json::Location::Root, shape, true, max_depth, options.enums),
+    ))
+}
+
+/// Map a JSON schema bundle and (field, pointer) projections into an AVRO value
+/// schema having a field of each projection, named by the projection and typed
+/// by the JSON schema at its pointer. Fields are ordered by name, and an object
+/// having each projected field at its pointer's value may be encoded with the schema.
+pub fn projections_to_avro(
+    json_schema: &str,
+    projections: &[(String, doc::Pointer)],
+    options: &SchemaOptions,
+) -> Result<apache_avro::Schema, Error> {
+    let mut fields: Vec<&str> = projections
+        .iter()
+        .map(|(field, _)| field.as_str())
+        .collect();
+    fields.sort();
+
+    for (index, field) in fields.iter().enumerate() {
+        if !schema::is_field_name(field) {
+            return Err(Error::ProjectionName(field.to_string()));
+        }
+        if *field == FLOW_EXTRA_NAME || fields.get(index + 1) == Some(field) {
+            return Err(Error::ProjectionConflict(field.to_string()));
+        }
+    }
+
+    let json_schema = doc::validation::build_bundle(json_schema)?;
+    let validator = doc::Validator::new(json_schema)?;
+    let shape = doc::Shape::infer(&validator.schemas()[0], validator.schema_index());
+    let max_depth = options.max_nesting_depth.unwrap_or(usize::MAX);
+
+    Ok(schema::projections_to_avro(
+        projections,
+        shape,
+        max_depth,
+        options.enums,
     ))
 }
 
@@ -67,3 +152,19 @@ pub fn encode<'s, 'n, N: doc::AsNode>(
 }
 
 pub use encode::encode_key;
+
+/// Can data written with the `writer` schema be read with the `reader` schema,
+/// following AVRO schema resolution?
+pub fn can_read(writer: &Schema, reader: &Schema) -> bool {
+    apache_avro::schema_compatibility::SchemaCompatibility::can_read(writer, reader)
+}
+
+/// Resolve a binary AVRO `datum` written with the `writer` schema into its
+/// representation under the `reader` schema, following AVRO schema resolution:
+/// fields of `reader` which `writer` lacks take their defaults, and fields
+/// which were renamed are matched through their aliases.
+pub fn resolve(writer: &Schema, reader: &Schema, datum: &[u8]) -> Result<Vec<u8>, Error> {
+    let value = apache_avro::from_avro_datum(writer, &mut &datum[..], Some(reader))
+        .map_err(Error::Resolve)?;
+    apache_avro::to_avro_datum(reader, value).map_err(Error::Resolve)
+}
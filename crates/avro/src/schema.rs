@@ -6,7 +6,16 @@ use std::fmt::Write;
 
 /// Map a Shape at the given location into an AVRO schema.
 /// If the location is not required and has no default, it may implicitly be none.
-pub fn shape_to_avro(loc: json::Location, shape: doc::Shape, required: bool) -> avro::Schema {
+/// Objects and arrays nested more than `max_depth` levels below this location
+/// are mapped into a schema holding their JSON encoding.
+/// If `enums`, string enumerations are mapped into AVRO enums where possible.
+pub fn shape_to_avro(
+    loc: json::Location,
+    shape: doc::Shape,
+    required: bool,
+    max_depth: usize,
+    enums: bool,
+) -> avro::Schema {
     let mut type_ = shape.type_;
 
     // Is this location nullable ? NULL may union with any other schema.
@@ -17,7 +26,23 @@ pub fn shape_to_avro(loc: json::Location, shape: doc::Shape, required: bool) ->
         false
     };
 
-    let base = if type_.overlaps(types::STRING) {
+    let symbols = match &shape.enum_ {
+        Some(values) if enums && type_ == types::STRING && shape.string.format.is_none() => {
+            enum_symbols(values)
+        }
+        _ => None,
+    };
+
+    let base = if let Some(symbols) = symbols {
+        avro::Schema::Enum(avro::EnumSchema {
+            name: location_to_name(loc),
+            aliases: None,
+            doc: None,
+            symbols,
+            default: None,
+            attributes: Default::default(),
+        })
+    } else if type_.overlaps(types::STRING) {
         match (type_ - types::STRING, shape.string.format) {
             // If the location may contain a fractional number, map to a double.
             // This could lose precision of a string number, but we're already using f64's,
@@ -43,11 +68,12 @@ pub fn shape_to_avro(loc: json::Location, shape: doc::Shape, required: bool) ->
         }
     } else {
         match type_ {
-            types::ARRAY => array_to_avro(loc, shape.array),
+            types::ARRAY | types::OBJECT if max_depth == 0 => raw_json_schema(loc),
+            types::ARRAY => array_to_avro(loc, shape.array, max_depth - 1, enums),
             types::BOOLEAN => avro::Schema::Boolean,
             types::INTEGER => avro::Schema::Long,
             types::INT_OR_FRAC | types::FRACTIONAL => avro::Schema::Double,
-            types::OBJECT => object_to_avro(loc, shape.object),
+            types::OBJECT => object_to_avro(loc, shape.object, max_depth - 1, enums),
             // Other combinations fall back to JSON encoding.
             _ => raw_json_schema(loc),
         }
@@ -74,7 +100,13 @@ pub fn key_to_avro(key: &[doc::Pointer], shape: doc::Shape) -> avro::Schema {
         let (shape, _) = shape.locate(ptr);
 
         let default = shape.default.as_ref().map(|d| d.0.clone());
-        let schema = shape_to_avro(loc_parts.push_prop(&name), shape.clone(), true);
+        let schema = shape_to_avro(
+            loc_parts.push_prop(&name),
+            shape.clone(),
+            true,
+            usize::MAX,
+            false,
+        );
 
         parts.push(avro::RecordField {
             aliases: None,
@@ -117,8 +149,68 @@ pub fn key_to_avro(key: &[doc::Pointer], shape: doc::Shape) -> avro::Schema {
     })
 }
 
+// Map (field, pointer) projections of a Shape into a flat Avro Record,
+// having a field of each projection in field name order.
+pub fn projections_to_avro(
+    projections: &[(String, doc::Pointer)],
+    shape: doc::Shape,
+    max_depth: usize,
+    enums: bool,
+) -> avro::Schema {
+    let loc = json::Location::Root;
+
+    let mut projections: Vec<_> = projections.iter().collect();
+    projections.sort_by(|(l, _), (r, _)| l.cmp(r));
+
+    let fields = projections
+        .into_iter()
+        .enumerate()
+        .map(|(position, (name, ptr))| {
+            let (shape, exists) = shape.locate(ptr);
+            let default = shape.default.as_ref().map(|d| d.0.clone());
+            let schema = shape_to_avro(
+                loc.push_prop(name),
+                shape.clone(),
+                exists == doc::shape::location::Exists::Must,
+                max_depth.saturating_sub(1),
+                enums,
+            );
+
+            avro::RecordField {
+                aliases: None,
+                custom_attributes: Default::default(),
+                default,
+                doc: None,
+                name: name.clone(),
+                order: avro::RecordFieldOrder::Ascending,
+                position,
+                schema,
+            }
+        })
+        .collect();
+
+    avro::Schema::Record(avro::RecordSchema {
+        name: location_to_name(loc),
+        aliases: None,
+        doc: None,
+        fields,
+        attributes: Default::default(),
+        lookup: Default::default(),
+    })
+}
+
+// Is `name` a valid Avro field name?
+pub fn is_field_name(name: &str) -> bool {
+    AVRO_FIELD_RE.is_match(name)
+}
+
 // Map an Object Shape into an Avro schema (a Record or Map).
-fn object_to_avro(loc: json::Location, obj: doc::shape::ObjShape) -> avro::Schema {
+fn object_to_avro(
+    loc: json::Location,
+    obj: doc::shape::ObjShape,
+    max_depth: usize,
+    enums: bool,
+) -> avro::Schema {
     let mut fields: Vec<avro::RecordField> = Vec::new();
     let mut extra = doc::Shape::nothing();
 
@@ -134,7 +226,7 @@ fn object_to_avro(loc: json::Location, obj: doc::shape::ObjShape) -> avro::Schem
     // If there are no explicit properties, but this object may have pattern or
     // additional properties, then interpret it as an Avro map.
     if extra.type_ != types::INVALID && obj.properties.is_empty() {
-        let schema = shape_to_avro(loc, extra, true);
+        let schema = shape_to_avro(loc, extra, true, max_depth, enums);
         return avro::Schema::Map(Box::new(schema));
     }
 
@@ -147,7 +239,13 @@ fn object_to_avro(loc: json::Location, obj: doc::shape::ObjShape) -> avro::Schem
             continue; // Cannot be represented under Avro's name restrictions.
         }
         let default = prop.shape.default.as_ref().map(|d| d.0.clone());
-        let schema = shape_to_avro(loc.push_prop(&prop.name), prop.shape, prop.is_required);
+        let schema = shape_to_avro(
+            loc.push_prop(&prop.name),
+            prop.shape,
+            prop.is_required,
+            max_depth,
+            enums,
+        );
 
         fields.push(avro::RecordField {
             aliases: None,
@@ -165,7 +263,13 @@ fn object_to_avro(loc: json::Location, obj: doc::shape::ObjShape) -> avro::Schem
     // This field MUST appear last in the record schema.
 
     if extra.type_ != types::INVALID {
-        let schema = shape_to_avro(loc.push_prop(FLOW_EXTRA_NAME), extra, true);
+        let schema = shape_to_avro(
+            loc.push_prop(FLOW_EXTRA_NAME),
+            extra,
+            true,
+            max_depth,
+            enums,
+        );
         let schema = avro::Schema::Map(Box::new(schema));
 
         fields.push(avro::RecordField {
@@ -191,7 +295,12 @@ fn object_to_avro(loc: json::Location, obj: doc::shape::ObjShape) -> avro::Schem
 }
 
 // Map an Array Shape into an Avro Array.
-fn array_to_avro(loc: json::Location, shape: doc::shape::ArrayShape) -> avro::Schema {
+fn array_to_avro(
+    loc: json::Location,
+    shape: doc::shape::ArrayShape,
+    max_depth: usize,
+    enums: bool,
+) -> avro::Schema {
     let mut items = doc::Shape::nothing();
 
     if let Some(addl) = shape.additional_items {
@@ -204,10 +313,37 @@ fn array_to_avro(loc: json::Location, shape: doc::shape::ArrayShape) -> avro::Sc
         items = doc::Shape::union(items, shape);
     }
 
-    let items = shape_to_avro(loc.push_prop("_items"), items, true);
+    let items = shape_to_avro(loc.push_prop("_items"), items, true, max_depth, enums);
     avro::Schema::Array(Box::new(items))
 }
 
+// Map enumerated values into AVRO enum symbols, ignoring null (which is
+// handled by a union). Returns None if any value isn't a string which is a
+// valid AVRO symbol, or if a symbol repeats, in which case the location is
+// mapped into a string instead.
+fn enum_symbols(values: &[serde_json::Value]) -> Option<Vec<String>> {
+    let mut symbols = Vec::with_capacity(values.len());
+
+    for value in values {
+        match value {
+            serde_json::Value::Null => continue,
+            serde_json::Value::String(s) if AVRO_FIELD_RE.is_match(s) => {
+                if symbols.contains(s) {
+                    return None;
+                }
+                symbols.push(s.clone());
+            }
+            _ => return None,
+        }
+    }
+
+    if symbols.is_empty() {
+        None
+    } else {
+        Some(symbols)
+    }
+}
+
 // Map a location into a special Schema holding a string-encoded JSON value.
 fn raw_json_schema(loc: json::Location) -> avro::Schema {
     let fields = vec![avro::RecordField {
@@ -318,6 +454,195 @@ mod test {
         insta::assert_json_snapshot!(schema_test(&fixture, &key));
     }
 
+    #[test]
+    fn test_max_nesting_depth() {
+        let fixture = json!({
+          "type": "object",
+          "properties": {
+            "id": {"type": "string"},
+            "one": {
+              "type": "object",
+              "properties": {
+                "two": {
+                  "type": "object",
+                  "properties": {"three": {"type": "integer"}},
+                  "required": ["three"],
+                },
+              },
+              "required": ["two"],
+              "additionalProperties": false,
+            },
+          },
+          "required": ["id", "one"],
+          "additionalProperties": false,
+        })
+        .to_string();
+        let key = vec![doc::Pointer::from_str("/id")];
+
+        let (_key, value) =
+            crate::json_schema_to_avro_with_max_depth(&fixture, &key, Some(2)).unwrap();
+
+        // The document and its `one` sub-object remain records,
+        // while `two` is mapped into a JSON-encoded string.
+        let schema = json!(&value);
+        assert_eq!(schema["name"], "root");
+        assert_eq!(schema["fields"][1]["type"]["name"], "one");
+        assert_eq!(
+            schema["fields"][1]["type"]["fields"][0]["type"],
+            json!({
+                "type": "record",
+                "name": "RawJSON",
+                "namespace": "root.one.two",
+                "fields": [{"name": "json", "type": "string"}],
+            })
+        );
+
+        // The remainder is encoded as a JSON string.
+        let doc = json!({"id": "a", "one": {"two": {"three": 3}}});
+        let mut buf = Vec::new();
+        crate::encode(&mut buf, &value, &doc).unwrap();
+        assert_eq!(&buf[3..], br#"{"three":3}"#);
+    }
+
+    #[test]
+    fn test_projections() {
+        let fixture = json!({
+          "type": "object",
+          "properties": {
+            "user": {
+              "type": "object",
+              "properties": {
+                "id": {"type": "integer"},
+                "name": {"type": "string"},
+              },
+              "required": ["id"],
+            },
+          },
+          "required": ["user"],
+        })
+        .to_string();
+        let projections = |fields: &[(&str, &str)]| -> Vec<_> {
+            fields
+                .iter()
+                .map(|(field, ptr)| (field.to_string(), doc::Pointer::from_str(ptr)))
+                .collect()
+        };
+        let options = crate::SchemaOptions::default();
+
+        let value = crate::projections_to_avro(
+            &fixture,
+            &projections(&[("user_name", "/user/name"), ("user_id", "/user/id")]),
+            &options,
+        )
+        .unwrap();
+
+        // Projected fields are flattened and renamed, in field name order.
+        let schema = json!(&value);
+        assert_eq!(
+            schema["fields"][0],
+            json!({"name": "user_id", "type": "long"})
+        );
+        assert_eq!(
+            schema["fields"][1],
+            json!({"name": "user_name", "type": ["string", "null"]})
+        );
+
+        // An object of projected fields is encoded with the schema,
+        // and missing fields are null.
+        let mut buf = Vec::new();
+        crate::encode(&mut buf, &value, &json!({"user_id": 42})).unwrap();
+        assert_eq!(buf, vec![84, 2]);
+
+        // Projections must have distinct and valid field names.
+        let err = crate::projections_to_avro(
+            &fixture,
+            &projections(&[("id", "/user/id"), ("id", "/user/name")]),
+            &options,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"projection field "id" is declared by more than one projection, or is reserved"#
+        );
+        let err = crate::projections_to_avro(
+            &fixture,
+            &projections(&[("user/id", "/user/id")]),
+            &options,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"projection field "user/id" is not a valid AVRO field name"#
+        );
+    }
+
+    #[test]
+    fn test_enums() {
+        let fixture = json!({
+          "type": "object",
+          "properties": {
+            "id": {"type": "string"},
+            "color": {"enum": ["red", "green", "blue"]},
+            "maybe_size": {"enum": ["small", "large", null]},
+            "invalid_symbol": {"enum": ["no spaces", "ok"]},
+            "mixed": {"enum": ["one", 2]},
+          },
+          "required": ["id", "color", "invalid_symbol", "mixed"],
+          "additionalProperties": false,
+        })
+        .to_string();
+        let key = vec![doc::Pointer::from_str("/id")];
+
+        let options = crate::SchemaOptions {
+            enums: true,
+            ..Default::default()
+        };
+        let (_key, value) =
+            crate::json_schema_to_avro_with_options(&fixture, &key, &options).unwrap();
+        let schema = json!(&value);
+        let field_type = |name: &str| {
+            schema["fields"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|field| field["name"] == name)
+                .unwrap()["type"]
+                .clone()
+        };
+
+        // A string enum becomes an Avro enum.
+        assert_eq!(
+            field_type("color"),
+            json!({
+                "type": "enum",
+                "name": "color",
+                "namespace": "root",
+                "symbols": ["blue", "green", "red"], // Sorted by the Shape.
+            })
+        );
+        // A nullable enum is a union with null.
+        assert_eq!(field_type("maybe_size")[0]["type"], "enum");
+        assert_eq!(field_type("maybe_size")[1], "null");
+        // Enums which can't be represented fall back to strings or JSON.
+        assert_eq!(field_type("invalid_symbol"), "string");
+        assert_ne!(field_type("mixed")["type"], "enum");
+
+        // Without the option, enums remain strings.
+        let (_key, value) = crate::json_schema_to_avro(&fixture, &key).unwrap();
+        assert_eq!(json!(&value)["fields"][0]["type"], "string");
+
+        // Enums encode as the index of their symbol.
+        let (_key, value) =
+            crate::json_schema_to_avro_with_options(&fixture, &key, &options).unwrap();
+        let doc = json!({"id": "a", "color": "red", "invalid_symbol": "ok", "mixed": 2});
+        let mut buf = Vec::new();
+        crate::encode(&mut buf, &value, &doc).unwrap();
+        assert_eq!(buf[0], 4); // Zig-zag encoding of index 2 (red).
+
+        let doc = json!({"id": "a", "color": "purple", "invalid_symbol": "ok", "mixed": 2});
+        assert!(crate::encode(&mut Vec::new(), &value, &doc).is_err());
+    }
+
     fn schema_test(json_schema: &str, key: &[doc::Pointer]) -> serde_json::Value {
         let (key, value) = crate::json_schema_to_avro(json_schema, key).unwrap();
 
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Seek, Write};
+
+/// Iterator of (key, document) pairs in key order.
+pub type SortedDocs = Box<dyn Iterator<Item = anyhow::Result<(String, serde_json::Value)>>>;
+
+/// LatestByKey retains the latest document of each key, as does log compaction.
+///
+/// Documents are held in memory until their approximate size exceeds a spill
+/// threshold, whereupon they're written as a sorted run to a temporary file.
+/// Iteration merges all runs in key order, where the document of a later run
+/// supersedes that of an earlier one.
+pub struct LatestByKey {
+    mem: BTreeMap<String, serde_json::Value>,
+    mem_bytes: usize,
+    runs: Vec<std::fs::File>,
+    spill_threshold: Option<usize>,
+}
+
+impl LatestByKey {
+    /// Build a LatestByKey which spills after holding `spill_threshold` bytes
+    /// of documents in memory, or which never spills if None.
+    pub fn new(spill_threshold: Option<usize>) -> Self {
+        Self {
+            mem: BTreeMap::new(),
+            mem_bytes: 0,
+            runs: Vec::new(),
+            spill_threshold,
+        }
+    }
+
+    /// Insert `doc` as the latest document of `key`.
+    pub fn insert(&mut self, key: String, doc: serde_json::Value) -> anyhow::Result<()> {
+        // Replaced documents aren't subtracted, so this over-estimates memory use.
+        self.mem_bytes += key.len() + serde_json::to_vec(&doc)?.len();
+        self.mem.insert(key, doc);
+
+        if matches!(self.spill_threshold, Some(threshold) if self.mem_bytes > threshold) {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Number of sorted runs which have been spilled to disk.
+    #[cfg(test)]
+    pub fn spilled_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Consume the LatestByKey into an iterator of (key, latest document), in key order.
+    pub fn into_sorted(mut self) -> anyhow::Result<SortedDocs> {
+        if self.runs.is_empty() {
+            return Ok(Box::new(self.mem.into_iter().map(Ok)));
+        }
+        if !self.mem.is_empty() {
+            self.spill()?;
+        }
+
+        let mut merge = Merge {
+            heads: Vec::new(),
+            readers: self
+                .runs
+                .into_iter()
+                .map(|file| std::io::BufReader::new(file).lines())
+                .collect(),
+        };
+        for index in 0..merge.readers.len() {
+            merge.heads.push(None);
+            merge.advance(index)?;
+        }
+        Ok(Box::new(merge))
+    }
+
+    fn spill(&mut self) -> anyhow::Result<()> {
+        let mut w = std::io::BufWriter::new(tempfile::tempfile()?);
+
+        for entry in std::mem::take(&mut self.mem) {
+            serde_json::to_writer(&mut w, &entry)?;
+            w.write_all(b"\n")?;
+        }
+        let mut file = w.into_inner().map_err(|err| err.into_error())?;
+        file.rewind()?;
+
+        tracing::debug!(
+            mem_bytes = self.mem_bytes,
+            runs = self.runs.len() + 1,
+            "spilled sorted run of latest documents"
+        );
+        self.runs.push(file);
+        self.mem_bytes = 0;
+
+        Ok(())
+    }
+}
+
+// Merge of sorted runs, with the current head entry of each run.
+struct Merge {
+    heads: Vec<Option<(String, serde_json::Value)>>,
+    readers: Vec<std::io::Lines<std::io::BufReader<std::fs::File>>>,
+}
+
+impl Merge {
+    fn advance(&mut self, index: usize) -> anyhow::Result<()> {
+        self.heads[index] = match self.readers[index].next() {
+            Some(line) => Some(serde_json::from_str(&line?)?),
+            None => None,
+        };
+        Ok(())
+    }
+}
+
+impl Iterator for Merge {
+    type Item = anyhow::Result<(String, serde_json::Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self
+            .heads
+            .iter()
+            .flatten()
+            .map(|(key, _)| key)
+            .min()?
+            .clone();
+        let mut latest = None;
+
+        // Runs are ordered oldest to newest, so the last run having `key` wins.
+        for index in 0..self.heads.len() {
+            if matches!(&self.heads[index], Some((k, _)) if *k == key) {
+                latest = self.heads[index].take();
+
+                if let Err(err) = self.advance(index) {
+                    return Some(Err(err));
+                }
+            }
+        }
+        latest.map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_latest_by_key_with_spilling() {
+        let mut spilling = LatestByKey::new(Some(256));
+        let mut in_memory = LatestByKey::new(None);
+
+        for n in 0..200 {
+            let (key, doc) = (format!("key-{:02}", (n * 7) % 23), json!({"n": n}));
+            spilling.insert(key.clone(), doc.clone()).unwrap();
+            in_memory.insert(key, doc).unwrap();
+        }
+        assert!(spilling.spilled_runs() > 1);
+        assert_eq!(in_memory.spilled_runs(), 0);
+
+        let spilled: Vec<_> = spilling
+            .into_sorted()
+            .unwrap()
+            .collect::<anyhow::Result<_>>()
+            .unwrap();
+        let expect: Vec<_> = in_memory
+            .into_sorted()
+            .unwrap()
+            .collect::<anyhow::Result<_>>()
+            .unwrap();
+
+        assert_eq!(spilled.len(), 23);
+        assert_eq!(spilled, expect);
+
+        // Each key holds its latest document.
+        assert_eq!(spilled[0], ("key-00".to_string(), json!({"n": 184})));
+        assert_eq!(spilled[22], ("key-22".to_string(), json!({"n": 197})));
+    }
+}
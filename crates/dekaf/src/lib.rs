@@ -5,8 +5,14 @@ use kafka_protocol::{
     protocol::{Builder, Decodable, Encodable},
 };
 
-mod topology;
-use topology::{fetch_all_collection_names, Collection, Partition};
+pub mod topology;
+pub use topology::{
+    set_control_plane_retry_policy, set_journal_client_build_limit, set_registration_rate_limit,
+    set_schema_normalizer, set_schema_registry_limit, set_tenant_query_limits, RetryPolicy,
+};
+use topology::{Collection, Partition};
+
+mod compact;
 
 mod read;
 use read::Read;
@@ -16,6 +22,8 @@ pub use session::Session;
 
 pub mod registry;
 
+pub mod telemetry;
+
 pub struct App {
     /// Anonymous API client for the Estuary control plane.
     pub anon_client: postgrest::Postgrest,
@@ -23,15 +31,28 @@ pub struct App {
     pub advertise_host: String,
     /// Port which is advertised for Kafka access.
     pub advertise_kafka_port: u16,
+    /// Whether enumerated topics are validated against Kafka's topic naming
+    /// constraints, omitting (and logging) those which violate them.
+    pub validate_topic_names: bool,
+    /// Mapper of collection catalog names into Kafka topic names.
+    pub topic_name_mapper: std::sync::Arc<dyn topology::TopicNameMapper>,
+    /// Strategy by which schema registry subjects name topics and their schemas.
+    pub subject_name_strategy: topology::SubjectNameStrategy,
+    /// Options of the Collections which are built to serve sessions and registry requests.
+    /// Authenticated clients build Collections with a copy of these Options whose
+    /// principal is their refresh token.
+    pub collection_options: topology::Options,
 }
 
 impl App {
+    /// Authenticate a refresh token, returning a control-plane client of its access
+    /// token and the Options of the Collections which it builds.
     #[tracing::instrument(level = "info", err(Debug, level = "warn"), skip(self, password))]
     async fn authenticate(
         &self,
         username: &str,
         password: &str,
-    ) -> anyhow::Result<postgrest::Postgrest> {
+    ) -> anyhow::Result<(postgrest::Postgrest, topology::Options)> {
         // The "username" will eventually hold session configuration state.
         // Reserve the ability to do this by ensuring it currently equals '{}'.
         if username != "{}" {
@@ -71,10 +92,16 @@ impl App {
             .json()
             .await?;
 
-        Ok(self
+        let client = self
             .anon_client
             .clone()
-            .insert_header("Authorization", format!("Bearer {access_token}")))
+            .insert_header("Authorization", format!("Bearer {access_token}"));
+        let options = topology::Options {
+            principal: Some(refresh_token_id),
+            ..self.collection_options.clone()
+        };
+
+        Ok((client, options))
     }
 }
 
@@ -1,7 +1,21 @@
-use super::{Collection, Partition};
-use futures::TryStreamExt;
-use gazette::journal::{ReadJsonLine, ReadJsonLines};
+use super::{
+    topology::{
+        map_partition_gone, DeletionRule, DocumentFilter, EncodeBuffer, EncodeBufferPool, Format,
+        MetaFieldsPolicy, PartitionFanOut, ReadMode, SchemaFormat, SchemaIds, TimestampPolicy,
+        WriterSchema, DEFAULT_READ_AHEAD, PUBLISHED_AT_FIELD,
+    },
+    Collection, Partition,
+};
+use anyhow::Context;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use gazette::journal::ReadJsonLine;
 use gazette::{broker, journal, uuid};
+use kafka_protocol::protocol::StrBytes;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Stream of documents read ahead of a Read.
+type ReadLines = BoxStream<'static, gazette::Result<ReadJsonLine>>;
 
 pub struct Read {
     // Journal offset to be served by this Read.
@@ -9,16 +23,33 @@ pub struct Read {
     pub(crate) offset: i64,
     // Most-recent journal write head observed by this Read.
     pub(crate) last_write_head: i64,
+    // Mod time of the fragment being read, or zero if it's not yet persisted.
+    fragment_mod_time: i64,
+    // Pool of buffers into which records are encoded, if any.
+    buffer_pool: Option<Arc<EncodeBufferPool>>,
+    // Fan-out of the journal, and the shard of it served by this Read, if any.
+    fan_out: Option<(PartitionFanOut, u32)>,
+    // Projected fields of encoded values, or empty if values are documents.
+    projections: Vec<(String, doc::Pointer)>,
+    // Record headers and the document locations of their values.
+    record_headers: Vec<(String, doc::Pointer)>,
 
-    key_ptr: Vec<doc::Pointer>, // Pointers to the document key.
-    key_schema: avro::Schema,   // Avro schema when encoding keys.
-    key_schema_id: u32,         // Registry ID of the key's schema.
-    meta_op_ptr: doc::Pointer,  // Location of document op (currently always `/_meta/op`).
-    not_before: uuid::Clock,    // Not before this clock.
-    stream: ReadJsonLines,      // Underlying document stream.
-    uuid_ptr: doc::Pointer,     // Location of document UUID.
-    value_schema: avro::Schema, // Avro schema when encoding values.
-    value_schema_id: u32,       // Registry ID of the value's schema.
+    format: Format,                 // Format of encoded record values.
+    journal: String,                // Journal being read.
+    key_ptr: Vec<doc::Pointer>,     // Pointers to the record key.
+    key_schema: avro::Schema,       // Avro schema when encoding keys.
+    deletion: DeletionRule,         // Rule by which documents are deletions.
+    filter: DocumentFilter,         // Filter of the documents which are read.
+    not_before: uuid::Clock,        // Not before this clock.
+    reducer: Option<Reducer>,       // Reducer of delta-update documents, if enabled.
+    schema_format: SchemaFormat,    // Format of the registered key and value schemas.
+    schema_ids: Arc<SchemaIds>,     // Registry IDs of the key and value schemas.
+    stream: ReadLines,              // Underlying document stream.
+    strip_meta: bool,               // Strip `/_meta` from encoded values.
+    timestamp: TimestampPolicy,     // Placement of document publish times.
+    uuid_ptr: Option<doc::Pointer>, // Location of document UUID, if any.
+    value_schema: avro::Schema,     // Avro schema when encoding values.
+    writer_schemas: WriterSchemas,  // Older value schemas which documents are migrated from.
 }
 
 impl Read {
@@ -27,11 +58,17 @@ impl Read {
         collection: &Collection,
         partition: &Partition,
         offset: i64,
-        key_schema_id: u32,
-        value_schema_id: u32,
-    ) -> Self {
+        schema_ids: Arc<SchemaIds>,
+    ) -> anyhow::Result<Self> {
         let (not_before_sec, _) = collection.not_before.to_unix();
 
+        let reducer = match collection.options.read_mode {
+            ReadMode::Documents => None,
+            ReadMode::ReduceDeltas => {
+                Reducer::for_schema(&collection.spec.write_schema_json, &collection.key_ptr)?
+            }
+        };
+
         let stream = client.read_json_lines(
             broker::ReadRequest {
                 offset,
@@ -40,26 +77,57 @@ impl Read {
                 begin_mod_time: not_before_sec as i64,
                 ..Default::default()
             },
-            // Each ReadResponse can be up to 130K. Buffer up to ~4MB so that
-            // `dekaf` can do lots of useful transcoding work while waiting for
-            // network delay of the next fetch request.
-            30,
+            0, // Unbuffered: documents are instead read ahead below.
+        );
+        // Each ReadResponse can be up to 130K. Read ahead up to ~4MB of documents
+        // (DEFAULT_READ_AHEAD documents of typical size) so that `dekaf` can do lots
+        // of useful transcoding work while waiting for network delay of the next
+        // fetch request, but bound the read-ahead so that a slow Kafka client
+        // pauses reads of the journal.
+        let stream = read_ahead(
+            stream,
+            collection.options.read_ahead.unwrap_or(DEFAULT_READ_AHEAD),
         );
 
-        Self {
+        Ok(Self {
             offset,
             last_write_head: offset,
+            fragment_mod_time: 0,
+            buffer_pool: collection.options.encode_buffer_pool.clone(),
+            fan_out: None,
+            projections: collection.projections.clone(),
+            record_headers: collection.options.record_headers.clone(),
 
-            key_ptr: collection.key_ptr.clone(),
+            format: collection.options.format,
+            journal: partition.spec.name.clone(),
+            key_ptr: collection.record_key_ptr.clone(),
             key_schema: collection.key_schema.clone(),
-            key_schema_id,
-            meta_op_ptr: doc::Pointer::from_str("/_meta/op"),
+            deletion: collection.deletion.clone(),
+            filter: collection.options.document_filter.clone(),
             not_before: collection.not_before,
+            reducer,
+            schema_format: collection.options.schema_format,
+            schema_ids,
             stream,
+            strip_meta: collection.options.meta_fields == MetaFieldsPolicy::Strip,
+            timestamp: collection.options.timestamp,
             uuid_ptr: collection.uuid_ptr.clone(),
             value_schema: collection.value_schema.clone(),
-            value_schema_id,
-        }
+            writer_schemas: WriterSchemas::new(
+                &collection.writer_schemas,
+                &collection.value_schema,
+            ),
+        })
+    }
+
+    /// Serve only `shard` of the collection's PartitionFanOut,
+    /// skipping documents of other shards.
+    pub fn with_fan_out_shard(mut self, collection: &Collection, shard: u32) -> Self {
+        self.fan_out = collection
+            .options
+            .partition_fan_out
+            .map(|fan_out| (fan_out, shard));
+        self
     }
 
     pub async fn next_batch(mut self, target_bytes: usize) -> anyhow::Result<(Self, bytes::Bytes)> {
@@ -72,14 +140,20 @@ impl Read {
 
         // We Avro encode into Vec instead of BytesMut because Vec is
         // better optimized for pushing a single byte at a time.
-        let mut tmp = Vec::new();
+        // A pooled buffer is returned to its pool as it's dropped.
+        let mut tmp = match &self.buffer_pool {
+            Some(pool) => pool.take(),
+            None => EncodeBuffer::default(),
+        };
         let mut buf = bytes::BytesMut::new();
 
         while records_bytes < target_bytes {
             let read = tokio::select! {
                 biased; // Attempt to read before yielding.
 
-                read = self.stream.try_next() => read?,
+                read = self.stream.try_next() => {
+                    read.map_err(|err| map_partition_gone(err, &self.journal))?
+                }
 
                 () = std::future::ready(()), if records_bytes != 0 => {
                     break; // Yield if we have records and the stream isn't ready.
@@ -90,40 +164,60 @@ impl Read {
             let (root, next_offset) = match read {
                 ReadJsonLine::Meta(response) => {
                     self.last_write_head = response.write_head;
+                    if let Some(fragment) = &response.fragment {
+                        self.fragment_mod_time = fragment.mod_time;
+                    }
                     continue;
                 }
                 ReadJsonLine::Doc { root, next_offset } => (root, next_offset),
             };
-            let Some(doc::ArchivedNode::String(uuid)) = self.uuid_ptr.query(root.get()) else {
-                anyhow::bail!(
-                    "document at offset {} does not have a valid UUID",
-                    self.offset
-                );
+            let (producer, clock, flags) = if let Some(uuid_ptr) = &self.uuid_ptr {
+                let Some(doc::ArchivedNode::String(uuid)) = uuid_ptr.query(root.get()) else {
+                    anyhow::bail!(
+                        "document at offset {} does not have a valid UUID",
+                        self.offset
+                    );
+                };
+                gazette::uuid::parse_str(uuid.as_str())?
+            } else {
+                // Without UUIDs, documents are read as non-ACKs which are
+                // timestamped by their ingestion into the journal.
+                (
+                    uuid::Producer::from_i64(0),
+                    ingest_clock(self.fragment_mod_time, std::time::SystemTime::now()),
+                    uuid::Flags(0),
+                )
             };
-            let (producer, clock, flags) = gazette::uuid::parse_str(uuid.as_str())?;
 
             if clock < self.not_before {
                 continue;
             }
+            if let Some((fan_out, shard)) = self.fan_out {
+                if fan_out.shard_of(next_offset - 1) != shard {
+                    self.offset = next_offset; // Document of another shard.
+                    continue;
+                }
+            }
 
             // Is this a non-content control document, such as a transaction ACK?
             let is_control = flags.is_ack();
-            // Is this a deletion?
-            let is_deletion = matches!(
-                self.meta_op_ptr.query(root.get()),
-                Some(doc::ArchivedNode::String(op)) if op.as_str() == "d",
-            );
+
+            if !is_control && !self.filter.matches(root.get()) {
+                self.offset = next_offset; // Document doesn't match the filter.
+                continue;
+            }
+            // Is this a deletion? Deletions are read as tombstones having a null value.
+            let is_deletion = !is_control && self.deletion.is_deletion(root.get());
 
             tmp.reserve(root.bytes().len()); // Avoid small allocations.
-            let (unix_seconds, unix_nanos) = clock.to_unix();
+            let (timestamp, published_at) = publish_time(self.timestamp, clock);
+            let (key_id, value_id) = self.schema_ids.get();
 
-            // Encode the key.
-            let key = if is_control {
+            // Encode the key. A collection having an empty key has null record keys.
+            let key = if is_control || self.key_ptr.is_empty() {
                 None
             } else {
-                tmp.push(0);
-                tmp.extend(self.key_schema_id.to_be_bytes());
-                () = avro::encode_key(&mut tmp, &self.key_schema, root.get(), &self.key_ptr)?;
+                () = self.encoder().encode_key(&mut tmp, key_id, root.get())?;
 
                 records_bytes += tmp.len();
                 buf.extend_from_slice(&tmp);
@@ -135,14 +229,42 @@ impl Read {
             let value = if is_control || is_deletion {
                 None
             } else {
-                tmp.push(0);
-                tmp.extend(self.value_schema_id.to_be_bytes());
-                () = avro::encode(&mut tmp, &self.value_schema, root.get())?;
+                // Reduced states span many documents, and aren't migrated.
+                // Their reductions may themselves be deletions.
+                let deleted = if let Some(reducer) = &mut self.reducer {
+                    let (reduced, deleted) = reducer.reduce(root.get())?;
+                    let deleted = deleted && matches!(self.deletion, DeletionRule::Reduction);
 
-                records_bytes += tmp.len();
-                buf.extend_from_slice(&tmp);
-                tmp.clear();
-                Some(buf.split().freeze())
+                    if !deleted {
+                        () = self.encoder().encode_value(
+                            &mut tmp,
+                            value_id,
+                            &reduced,
+                            published_at.as_deref(),
+                            None,
+                        )?;
+                    }
+                    deleted
+                } else {
+                    let writer_schema = self.writer_schemas.select(clock.to_unix().0 as i64);
+                    () = self.encoder().encode_value(
+                        &mut tmp,
+                        value_id,
+                        root.get(),
+                        published_at.as_deref(),
+                        writer_schema,
+                    )?;
+                    false
+                };
+
+                if deleted {
+                    None
+                } else {
+                    records_bytes += tmp.len();
+                    buf.extend_from_slice(&tmp);
+                    tmp.clear();
+                    Some(buf.split().freeze())
+                }
             };
 
             self.offset = next_offset;
@@ -162,22 +284,34 @@ impl Read {
             // as offset for efficient record batch packing.
             let kafka_offset = next_offset - 1;
 
+            let headers = if is_control {
+                Default::default()
+            } else {
+                record_headers(root.get(), &self.record_headers)
+            };
+            records_bytes += headers
+                .iter()
+                .map(|(name, value)| name.len() + value.as_ref().map_or(0, |v| v.len()))
+                .sum::<usize>();
+
             records.push(Record {
                 control: false,
-                headers: Default::default(),
+                headers,
                 key,
                 offset: kafka_offset,
                 partition_leader_epoch: 1,
                 producer_epoch: 1,
                 producer_id: producer.as_i64(),
                 sequence: kafka_offset as i32,
-                timestamp: unix_seconds as i64 * 1000 + unix_nanos as i64 / 1_000_000, // Map into millis.
+                timestamp,
                 timestamp_type: TimestampType::LogAppend,
                 transactional: false,
                 value,
             });
         }
 
+        drop(tmp);
+
         let opts = RecordEncodeOptions {
             compression: Compression::Lz4,
             version: 2,
@@ -197,4 +331,1026 @@ impl Read {
 
         Ok((self, buf.freeze()))
     }
+
+    /// Encoder of the record keys and values of this Read.
+    fn encoder(&self) -> RecordEncoder<'_> {
+        RecordEncoder {
+            format: self.format,
+            schema_format: self.schema_format,
+            key_ptr: &self.key_ptr,
+            key_schema: &self.key_schema,
+            value_schema: &self.value_schema,
+            projections: &self.projections,
+            strip_meta: self.strip_meta,
+        }
+    }
+}
+
+/// RecordEncoder encodes documents into the keys and values of Kafka records.
+/// It's used by both Read and `Collection::encode_key` / `encode_value`,
+/// so that records encoded by either have identical bytes.
+pub(crate) struct RecordEncoder<'a> {
+    pub format: Format,                            // Format of encoded record values.
+    pub schema_format: SchemaFormat,               // Format of the registered schemas.
+    pub key_ptr: &'a [doc::Pointer],               // Pointers to the record key.
+    pub key_schema: &'a avro::Schema,              // Avro schema when encoding keys.
+    pub value_schema: &'a avro::Schema,            // Avro schema when encoding values.
+    pub projections: &'a [(String, doc::Pointer)], // Projected fields of values, if any.
+    pub strip_meta: bool,                          // Strip `/_meta` from encoded values.
+}
+
+impl<'a> RecordEncoder<'a> {
+    /// Build a RecordEncoder of the records of `collection`.
+    pub fn new(collection: &'a Collection) -> Self {
+        Self {
+            format: collection.options.format,
+            schema_format: collection.options.schema_format,
+            key_ptr: &collection.record_key_ptr,
+            key_schema: &collection.key_schema,
+            value_schema: &collection.value_schema,
+            projections: &collection.projections,
+            strip_meta: collection.options.meta_fields == MetaFieldsPolicy::Strip,
+        }
+    }
+
+    /// Encode the key of document `node` into buffer `b`,
+    /// following a wire-format header of registered key schema `key_id`.
+    pub fn encode_key<N: doc::AsNode>(
+        &self,
+        b: &mut Vec<u8>,
+        key_id: u32,
+        node: &N,
+    ) -> anyhow::Result<()> {
+        b.push(0);
+        b.extend(key_id.to_be_bytes());
+
+        match self.schema_format {
+            SchemaFormat::JsonSchema => {
+                () = encode_json_key(b, node, self.key_ptr)?;
+            }
+            SchemaFormat::Avro => {
+                () = avro::encode_key(b, self.key_schema, node, self.key_ptr)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode a document `node` as a record value into buffer `b`,
+    /// having `published_at` as its PUBLISHED_AT_FIELD if set.
+    /// If `writer_schema` is set, the document is migrated from it.
+    /// Values other than MessagePack follow a wire-format header of
+    /// registered value schema `value_id`.
+    pub fn encode_value<N: doc::AsNode>(
+        &self,
+        b: &mut Vec<u8>,
+        value_id: u32,
+        node: &N,
+        published_at: Option<&str>,
+        writer_schema: Option<&avro::Schema>,
+    ) -> anyhow::Result<()> {
+        if !self.strip_meta && published_at.is_none() && self.projections.is_empty() {
+            return self.encode_value_inner(b, value_id, node, writer_schema);
+        }
+        let mut doc = if !self.projections.is_empty() {
+            project_fields(node, self.projections)
+        } else if self.strip_meta {
+            strip_meta(node)?
+        } else {
+            serde_json::to_value(doc::SerPolicy::noop().on(node))?
+        };
+        if let (Some(published_at), Some(obj)) = (published_at, doc.as_object_mut()) {
+            obj.insert(PUBLISHED_AT_FIELD.to_string(), published_at.into());
+        }
+        self.encode_value_inner(b, value_id, &doc, writer_schema)
+    }
+
+    fn encode_value_inner<N: doc::AsNode>(
+        &self,
+        b: &mut Vec<u8>,
+        value_id: u32,
+        node: &N,
+        writer_schema: Option<&avro::Schema>,
+    ) -> anyhow::Result<()> {
+        match self.format {
+            Format::Avro if self.schema_format == SchemaFormat::JsonSchema => {
+                b.push(0);
+                b.extend(value_id.to_be_bytes());
+                () = serde_json::to_writer(b, &doc::SerPolicy::noop().on(node))?;
+            }
+            Format::Avro => {
+                b.push(0);
+                b.extend(value_id.to_be_bytes());
+
+                if let Some(writer_schema) = writer_schema {
+                    () = encode_migrated(b, writer_schema, self.value_schema, node)?;
+                } else {
+                    () = avro::encode(b, self.value_schema, node)?;
+                }
+            }
+            Format::MessagePack => {
+                () = encode_msgpack(b, self.value_schema, node)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Project document `node` into an object having each of `projections`,
+/// as a field holding the value at its pointer. Absent values are omitted.
+fn project_fields<N: doc::AsNode>(
+    node: &N,
+    projections: &[(String, doc::Pointer)],
+) -> serde_json::Value {
+    let fields = projections
+        .iter()
+        .filter_map(|(field, ptr)| {
+            let value = ptr.query(node)?;
+            let value = serde_json::to_value(doc::SerPolicy::noop().on(value)).ok()?;
+            Some((field.clone(), value))
+        })
+        .collect();
+
+    serde_json::Value::Object(fields)
+}
+
+/// Map document `node` into Kafka record headers, each having the value at its
+/// pointer. Strings are header values as-is, and other values are their JSON
+/// encodings. Headers whose pointers don't resolve within `node` are omitted.
+fn record_headers<N: doc::AsNode>(
+    node: &N,
+    headers: &[(String, doc::Pointer)],
+) -> kafka_protocol::indexmap::IndexMap<StrBytes, Option<bytes::Bytes>> {
+    headers
+        .iter()
+        .filter_map(|(name, ptr)| {
+            let value = serde_json::to_value(doc::SerPolicy::noop().on(ptr.query(node)?)).ok()?;
+            let value = match value {
+                serde_json::Value::String(value) => value.into_bytes(),
+                value => value.to_string().into_bytes(),
+            };
+            Some((StrBytes::from_string(name.clone()), Some(value.into())))
+        })
+        .collect()
+}
+
+/// Poll `inner` from a spawned task, passing up to `capacity` items through a
+/// bounded channel. When the channel is full the task stops polling `inner`,
+/// pausing its reads until the consumer of the returned stream catches up.
+fn read_ahead<S>(inner: S, capacity: usize) -> BoxStream<'static, S::Item>
+where
+    S: futures::Stream + Send + 'static,
+    S::Item: Send + 'static,
+{
+    let (tx, rx) = tokio::sync::mpsc::channel(capacity.max(1));
+
+    tokio::spawn(async move {
+        tokio::pin!(inner);
+
+        while let Some(item) = inner.next().await {
+            if tx.send(item).await.is_err() {
+                break; // Read was dropped.
+            }
+        }
+    });
+
+    futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    })
+    .boxed()
+}
+
+/// Map the mod time of a fragment into a Clock which approximates when its
+/// documents were ingested. A fragment which isn't yet persisted has a zero
+/// mod time, and its documents were ingested at or before `now`.
+fn ingest_clock(fragment_mod_time: i64, now: std::time::SystemTime) -> uuid::Clock {
+    if fragment_mod_time > 0 {
+        return uuid::Clock::from_unix(fragment_mod_time as u64, 0);
+    }
+    let now = now
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    uuid::Clock::from_unix(now.as_secs(), now.subsec_nanos())
+}
+
+/// Map a document's publish `clock` into its Kafka record timestamp in millis,
+/// which is -1 (no timestamp) if it's not placed in the record, and its
+/// RFC 3339 PUBLISHED_AT_FIELD value if it's placed in the record value.
+pub(crate) fn publish_time(policy: TimestampPolicy, clock: uuid::Clock) -> (i64, Option<String>) {
+    let (unix_seconds, unix_nanos) = clock.to_unix();
+
+    let timestamp = if policy.in_record() {
+        unix_seconds as i64 * 1000 + unix_nanos as i64 / 1_000_000 // Map into millis.
+    } else {
+        -1
+    };
+    let published_at = if policy.in_value() {
+        let nanos = unix_seconds as i128 * 1_000_000_000 + unix_nanos as i128;
+        time::OffsetDateTime::from_unix_timestamp_nanos(nanos)
+            .ok()
+            .and_then(|dt| {
+                dt.format(&time::format_description::well_known::Rfc3339)
+                    .ok()
+            })
+    } else {
+        None
+    };
+    (timestamp, published_at)
+}
+
+/// WriterSchemas are the schemas under which documents were written, by their
+/// registration times, for migration into a reader schema. Each is compared with
+/// the reader schema once, as WriterSchemas are built, and a writer schema which
+/// is the reader schema is None: its documents needn't be migrated.
+struct WriterSchemas(Vec<(i64, Option<avro::Schema>)>);
+
+impl WriterSchemas {
+    fn new(writer_schemas: &[WriterSchema], reader: &avro::Schema) -> Self {
+        let reader = reader.canonical_form();
+
+        Self(
+            writer_schemas
+                .iter()
+                .map(|writer| {
+                    let is_reader = writer.schema.canonical_form() == reader;
+                    (
+                        writer.registered_at,
+                        (!is_reader).then(|| writer.schema.clone()),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Select the schema which a document published at `published` unix seconds
+    /// was written under, being the last writer schema registered at or before
+    /// that time. Returns None if that's the reader schema, or if the document
+    /// predates all writer schemas and its writer schema isn't known.
+    fn select(&self, published: i64) -> Option<&avro::Schema> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(registered_at, _)| *registered_at <= published)?
+            .1
+            .as_ref()
+    }
+}
+
+/// Encode document `node` with its `writer` schema and resolve it into
+/// the `reader` schema, appending to buffer `b`. Documents which don't
+/// conform to `writer`, or which can't be resolved, are encoded with `reader`.
+fn encode_migrated<N: doc::AsNode>(
+    b: &mut Vec<u8>,
+    writer: &avro::Schema,
+    reader: &avro::Schema,
+    node: &N,
+) -> anyhow::Result<()> {
+    let mut datum = Vec::new();
+
+    let migrated = match avro::encode(&mut datum, writer, node) {
+        Ok(()) => avro::resolve(writer, reader, &datum),
+        Err(err) => Err(err),
+    };
+
+    match migrated {
+        Ok(migrated) => b.extend(migrated),
+        Err(err) => {
+            tracing::debug!(?err, "failed to migrate document from its writer schema");
+            () = avro::encode(b, reader, node)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encode the components of `key_ptr` within document `node` as a JSON array,
+/// where components which don't exist are null.
+pub(crate) fn encode_json_key<N: doc::AsNode>(
+    b: &mut Vec<u8>,
+    node: &N,
+    key_ptr: &[doc::Pointer],
+) -> anyhow::Result<()> {
+    let ser = doc::SerPolicy::noop();
+    let key: Vec<serde_json::Value> = key_ptr
+        .iter()
+        .map(|ptr| match ptr.query(node) {
+            Some(component) => serde_json::to_value(ser.on(component)),
+            None => Ok(serde_json::Value::Null),
+        })
+        .collect::<serde_json::Result<_>>()?;
+
+    Ok(serde_json::to_writer(b, &key)?)
+}
+
+/// Copy the document `node` with its top-level `_meta` property removed.
+/// Removal is required even if the value schema has no `_meta` field,
+/// as it would otherwise be encoded as an extra property of the document.
+fn strip_meta<N: doc::AsNode>(node: &N) -> anyhow::Result<serde_json::Value> {
+    let mut doc = serde_json::to_value(doc::SerPolicy::noop().on(node))?;
+    if let Some(obj) = doc.as_object_mut() {
+        obj.remove("_meta");
+    }
+    Ok(doc)
+}
+
+/// Encode a document `node` as MessagePack into buffer `b`.
+/// Object properties are ordered by the fields of their corresponding
+/// record within Avro `schema`, followed by any other properties.
+pub fn encode_msgpack<N: doc::AsNode>(
+    b: &mut Vec<u8>,
+    schema: &avro::Schema,
+    node: &N,
+) -> anyhow::Result<()> {
+    rmp_serde::encode::write(
+        b,
+        &Ordered {
+            schema: Some(schema),
+            node,
+        },
+    )?;
+    Ok(())
+}
+
+// Ordered serializes `node` with object properties ordered by `schema`.
+struct Ordered<'s, 'n, N: doc::AsNode> {
+    schema: Option<&'s avro::Schema>,
+    node: &'n N,
+}
+
+impl<'s, 'n, N: doc::AsNode> serde::Serialize for Ordered<'s, 'n, N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use doc::{Field, Fields};
+        use serde::ser::{SerializeMap, SerializeSeq};
+
+        // Select the schema (or union variant) which matches `pred`.
+        let variant = |pred: fn(&avro::Schema) -> bool| -> Option<&'s avro::Schema> {
+            match self.schema {
+                Some(avro::Schema::Union(union)) => union.variants().iter().find(|v| pred(v)),
+                Some(schema) if pred(schema) => Some(schema),
+                _ => None,
+            }
+        };
+
+        match self.node.as_node() {
+            doc::Node::Array(items) => {
+                let items_schema = match variant(|s| matches!(s, avro::Schema::Array(_))) {
+                    Some(avro::Schema::Array(items)) => Some(items.as_ref()),
+                    _ => None,
+                };
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for node in items {
+                    seq.serialize_element(&Ordered {
+                        schema: items_schema,
+                        node,
+                    })?;
+                }
+                seq.end()
+            }
+            doc::Node::Object(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+
+                match variant(|s| matches!(s, avro::Schema::Record(_) | avro::Schema::Map(_))) {
+                    Some(avro::Schema::Record(record)) => {
+                        for record_field in &record.fields {
+                            if let Some(field) = fields.get(&record_field.name) {
+                                map.serialize_entry(
+                                    field.property(),
+                                    &Ordered {
+                                        schema: Some(&record_field.schema),
+                                        node: field.value(),
+                                    },
+                                )?;
+                            }
+                        }
+                        for field in fields.iter() {
+                            if !record.fields.iter().any(|f| f.name == field.property()) {
+                                map.serialize_entry(
+                                    field.property(),
+                                    &Ordered {
+                                        schema: None,
+                                        node: field.value(),
+                                    },
+                                )?;
+                            }
+                        }
+                    }
+                    values => {
+                        let values = match values {
+                            Some(avro::Schema::Map(values)) => Some(values.as_ref()),
+                            _ => None,
+                        };
+                        for field in fields.iter() {
+                            map.serialize_entry(
+                                field.property(),
+                                &Ordered {
+                                    schema: values,
+                                    node: field.value(),
+                                },
+                            )?;
+                        }
+                    }
+                }
+                map.end()
+            }
+            _ => doc::SerPolicy::noop().on(self.node).serialize(serializer),
+        }
+    }
+}
+
+/// Reducer materializes the current state of each key of a collection whose
+/// documents are partial "delta" updates, by reducing each document into the
+/// prior state of its key using the reduction annotations of the collection schema.
+///
+/// A Reducer lives only as long as its Read, and holds the reduced state of
+/// every key the Read has observed and not since deleted: its memory grows with
+/// the number of distinct live keys of the journal, and is released with the Read.
+/// A Read which begins at an offset other than the start of its journal (or a
+/// Read which replaces a prior one of the same journal) therefore materializes
+/// only the deltas which it has itself read, and ReduceDeltas is appropriate
+/// only for collections having a bounded number of live keys.
+pub struct Reducer {
+    key_ptr: Vec<doc::Pointer>,
+    state: HashMap<String, serde_json::Value>,
+    validator: doc::Validator,
+}
+
+impl Reducer {
+    /// Build a Reducer for the given JSON (write) schema, or return None
+    /// if the schema's documents are not reduced as delta updates.
+    pub fn for_schema(json_schema: &str, key_ptr: &[doc::Pointer]) -> anyhow::Result<Option<Self>> {
+        let bundle = doc::validation::build_bundle(json_schema)?;
+        let validator = doc::Validator::new(bundle)?;
+        let shape = doc::Shape::infer(&validator.schemas()[0], validator.schema_index());
+
+        // A document which is wholly replaced by its successor is not a delta.
+        if matches!(
+            shape.reduction,
+            doc::shape::Reduction::Unset
+                | doc::shape::Reduction::Strategy(doc::reduce::Strategy::LastWriteWins(_))
+        ) {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            key_ptr: key_ptr.to_vec(),
+            state: HashMap::new(),
+            validator,
+        }))
+    }
+
+    /// Reduce `rhs` into the current state of its key, returning the updated state
+    /// and whether the reduction deleted it, in which case the key's state is cleared.
+    pub fn reduce<N: doc::AsNode>(&mut self, rhs: &N) -> anyhow::Result<(serde_json::Value, bool)> {
+        let ser = doc::SerPolicy::noop();
+
+        let key = self
+            .key_ptr
+            .iter()
+            .map(|ptr| ptr.query(rhs).map(|node| ser.on(node)))
+            .collect::<Vec<_>>();
+        let key = serde_json::to_string(&key)?;
+
+        // Reduction annotations are outcomes of validation, and a document which
+        // fails validation cannot be reduced.
+        let rhs_valid = self
+            .validator
+            .validate(None, rhs)?
+            .ok()
+            .context("delta document failed validation against its collection schema")?;
+
+        let reduced = match self.state.get(&key) {
+            Some(lhs) => {
+                let alloc = doc::HeapNode::new_allocator();
+                let lhs = doc::HeapNode::from_node(lhs, &alloc);
+                let rhs = doc::HeapNode::from_node(rhs, &alloc);
+
+                let (reduced, deleted) = doc::reduce::reduce::<serde_json::Value>(
+                    doc::LazyNode::Heap(&lhs),
+                    doc::LazyNode::Heap(&rhs),
+                    rhs_valid,
+                    &alloc,
+                    true, // LHS is the left-most document we've read.
+                )?;
+                (serde_json::to_value(ser.on(&reduced))?, deleted)
+            }
+            None => (serde_json::to_value(ser.on(rhs))?, false),
+        };
+
+        if reduced.1 {
+            self.state.remove(&key);
+        } else {
+            self.state.insert(key, reduced.0.clone());
+        }
+        Ok(reduced)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        encode_json_key, encode_migrated, encode_msgpack, ingest_clock, project_fields,
+        publish_time, read_ahead, record_headers, strip_meta, ReadJsonLine, Reducer, StreamExt,
+        TimestampPolicy, WriterSchema, WriterSchemas,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_reduce_delta_documents() {
+        let key = vec![doc::Pointer::from_str("/key")];
+
+        let schema = json!({
+            "type": "object",
+            "reduce": {"strategy": "merge"},
+            "properties": {
+                "key": {"type": "string"},
+                "count": {"type": "integer", "reduce": {"strategy": "sum"}},
+                "tags": {"type": "array", "reduce": {"strategy": "append"}},
+            },
+            "required": ["key"],
+        })
+        .to_string();
+
+        let mut reducer = Reducer::for_schema(&schema, &key)
+            .unwrap()
+            .expect("schema reduces delta updates");
+
+        assert_eq!(
+            reducer
+                .reduce(&json!({"key": "a", "count": 1, "tags": ["x"]}))
+                .unwrap()
+                .0,
+            json!({"key": "a", "count": 1, "tags": ["x"]}),
+        );
+        assert_eq!(
+            reducer.reduce(&json!({"key": "b", "count": 5})).unwrap().0,
+            json!({"key": "b", "count": 5}),
+        );
+        assert_eq!(
+            reducer
+                .reduce(&json!({"key": "a", "count": 2, "tags": ["y"]}))
+                .unwrap()
+                .0,
+            json!({"key": "a", "count": 3, "tags": ["x", "y"]}),
+        );
+
+        // Schemas without delta reductions don't build a Reducer.
+        let schema = json!({
+            "type": "object",
+            "properties": {"key": {"type": "string"}},
+        })
+        .to_string();
+
+        assert!(Reducer::for_schema(&schema, &key).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_reduce_delta_deletions() {
+        let key = vec![doc::Pointer::from_str("/key")];
+
+        // Documents having `deleted` delete the reduced state of their key.
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "key": {"type": "string"},
+                "count": {"type": "integer", "reduce": {"strategy": "sum"}},
+            },
+            "required": ["key"],
+            "if": {"required": ["deleted"]},
+            "then": {"reduce": {"strategy": "merge", "delete": true}},
+            "else": {"reduce": {"strategy": "merge"}},
+        })
+        .to_string();
+
+        let mut reducer = Reducer::for_schema(&schema, &key)
+            .unwrap()
+            .expect("schema reduces delta updates");
+
+        assert_eq!(
+            reducer.reduce(&json!({"key": "a", "count": 1})).unwrap(),
+            (json!({"key": "a", "count": 1}), false),
+        );
+        let (_reduced, deleted) = reducer
+            .reduce(&json!({"key": "a", "count": 2, "deleted": true}))
+            .unwrap();
+        assert!(deleted);
+
+        // The deleted key's reductions begin anew.
+        assert_eq!(
+            reducer.reduce(&json!({"key": "a", "count": 5})).unwrap(),
+            (json!({"key": "a", "count": 5}), false),
+        );
+
+        // Documents which fail validation are errors, and don't alter the key's state.
+        let err = reducer
+            .reduce(&json!({"key": "a", "count": "six"}))
+            .unwrap_err();
+        assert!(err.to_string().contains("failed validation"));
+
+        assert_eq!(
+            reducer.reduce(&json!({"key": "a", "count": 1})).unwrap(),
+            (json!({"key": "a", "count": 6}), false),
+        );
+    }
+
+    #[test]
+    fn test_msgpack_round_trip() {
+        let key = vec![doc::Pointer::from_str("/key")];
+
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "key": {"type": "string"},
+                "nested": {
+                    "type": "object",
+                    "properties": {"b": {"type": "integer"}, "a": {"type": "boolean"}},
+                },
+                "list": {"type": "array", "items": {"type": "number"}},
+            },
+            "required": ["key"],
+        })
+        .to_string();
+        let (_key_schema, value_schema) = avro::json_schema_to_avro(&schema, &key).unwrap();
+
+        let document = json!({
+            "key": "a",
+            "nested": {"a": true, "b": 42},
+            "list": [1.5, -2, 3],
+            "extra": {"z": null, "y": "hello"},
+        });
+
+        let mut buf = Vec::new();
+        encode_msgpack(&mut buf, &value_schema, &document).unwrap();
+
+        let decoded: serde_json::Value = rmp_serde::from_slice(&buf).unwrap();
+        assert_eq!(decoded, document);
+    }
+
+    #[test]
+    fn test_strip_meta() {
+        let key = vec![doc::Pointer::from_str("/key")];
+
+        // Additional properties are permitted, and are encoded as `_flow_extra`.
+        let schema = json!({
+            "type": "object",
+            "properties": {"key": {"type": "string"}},
+            "required": ["key"],
+        })
+        .to_string();
+        let (_key_schema, value_schema) = avro::json_schema_to_avro(&schema, &key).unwrap();
+
+        let with_meta = json!({"_meta": {"uuid": "an-uuid"}, "key": "a", "other": 1});
+        let without_meta = json!({"key": "a", "other": 1});
+
+        assert_eq!(strip_meta(&with_meta).unwrap(), without_meta);
+        assert_eq!(strip_meta(&json!([1, 2])).unwrap(), json!([1, 2]));
+
+        let encode = |doc: &serde_json::Value| {
+            let mut b = Vec::new();
+            () = avro::encode(&mut b, &value_schema, doc).unwrap();
+            b
+        };
+        // When included, `_meta` is encoded as an extra property.
+        assert_ne!(encode(&with_meta), encode(&without_meta));
+        // When stripped, it's not.
+        assert_eq!(
+            encode(&strip_meta(&with_meta).unwrap()),
+            encode(&without_meta)
+        );
+    }
+
+    #[test]
+    fn test_record_headers() {
+        let headers = vec![
+            ("uuid".to_string(), doc::Pointer::from_str("/_meta/uuid")),
+            ("region".to_string(), doc::Pointer::from_str("/region")),
+            ("file".to_string(), doc::Pointer::from_str("/_meta/file")),
+            ("count".to_string(), doc::Pointer::from_str("/count")),
+        ];
+        let doc = json!({"_meta": {"uuid": "an-uuid"}, "region": {"id": 7}, "count": 42});
+
+        // Strings are used as-is, others are JSON, and unresolved pointers are omitted.
+        let mapped: Vec<_> = record_headers(&doc, &headers)
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.unwrap()))
+            .collect();
+        assert_eq!(
+            mapped,
+            vec![
+                ("uuid".to_string(), bytes::Bytes::from_static(b"an-uuid")),
+                (
+                    "region".to_string(),
+                    bytes::Bytes::from_static(br#"{"id":7}"#)
+                ),
+                ("count".to_string(), bytes::Bytes::from_static(b"42")),
+            ]
+        );
+        assert!(record_headers(&doc, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_project_fields() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "user": {
+                    "type": "object",
+                    "properties": {"id": {"type": "integer"}, "name": {"type": "string"}},
+                    "required": ["id"],
+                },
+            },
+            "required": ["user"],
+        })
+        .to_string();
+        let projections = vec![
+            ("user_id".to_string(), doc::Pointer::from_str("/user/id")),
+            (
+                "user_name".to_string(),
+                doc::Pointer::from_str("/user/name"),
+            ),
+        ];
+        let value_schema =
+            avro::projections_to_avro(&schema, &projections, &Default::default()).unwrap();
+
+        // Absent projections are omitted, and are encoded as null.
+        let doc = json!({"_meta": {"uuid": "an-uuid"}, "user": {"id": 42}});
+        let projected = project_fields(&doc, &projections);
+        assert_eq!(projected, json!({"user_id": 42}));
+
+        let mut b = Vec::new();
+        () = avro::encode(&mut b, &value_schema, &projected).unwrap();
+        assert_eq!(b, vec![84, 2]);
+
+        let doc = json!({"user": {"id": 7, "name": "Jo", "other": true}});
+        assert_eq!(
+            project_fields(&doc, &projections),
+            json!({"user_id": 7, "user_name": "Jo"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_ahead_pauses() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let produced = Arc::new(AtomicUsize::new(0));
+        let produced_clone = produced.clone();
+
+        let inner = futures::stream::iter(0..).inspect(move |_| {
+            produced_clone.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut stream = read_ahead(inner, 2);
+
+        let settle = || async {
+            for _ in 0..100 {
+                tokio::task::yield_now().await;
+            }
+        };
+
+        // Without a consumer, the reader pauses once the channel is full
+        // (with one more item awaiting a send).
+        settle().await;
+        assert_eq!(produced.load(Ordering::SeqCst), 3);
+        settle().await;
+        assert_eq!(produced.load(Ordering::SeqCst), 3);
+
+        // As items are consumed, the reader resumes.
+        assert_eq!(stream.next().await, Some(0));
+        assert_eq!(stream.next().await, Some(1));
+        settle().await;
+        assert_eq!(produced.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_read_and_collection_encode_identically() {
+        use crate::topology::test::{collection_fixture, partition_fixture, spec_fixture};
+        use crate::topology::{add_published_at_field, Format, MetaFieldsPolicy, RegistryId};
+
+        let doc = json!({
+            "id": "a-key",
+            "_meta": {"uuid": "9f2952f3-c6a3-11ea-8802-080607050309"},
+        });
+        let partition = partition_fixture("acmeCo/things/pivot=00");
+
+        for format in [Format::Avro, Format::MessagePack] {
+            for timestamp in [TimestampPolicy::RecordTimestamp, TimestampPolicy::Both] {
+                let mut collection = collection_fixture(spec_fixture());
+                collection.options.format = format;
+                collection.options.meta_fields = MetaFieldsPolicy::Strip;
+                collection.options.timestamp = timestamp;
+                if timestamp.in_value() {
+                    collection.value_schema =
+                        add_published_at_field(collection.value_schema.clone());
+                }
+                let (key_id, value_id) = (
+                    RegistryId {
+                        format: collection.options.schema_format,
+                        id: 7,
+                    },
+                    RegistryId {
+                        format: collection.options.schema_format,
+                        id: 8,
+                    },
+                );
+
+                let read = super::Read::new(
+                    collection.journal_client.clone(),
+                    &collection,
+                    &partition,
+                    0,
+                    std::sync::Arc::new(crate::topology::SchemaIds::new(7, 8)),
+                )
+                .unwrap();
+
+                let (_producer, clock, _flags) =
+                    gazette::uuid::parse_str("9f2952f3-c6a3-11ea-8802-080607050309").unwrap();
+                let (_timestamp, published_at) = publish_time(read.timestamp, clock);
+
+                let (mut key, mut value) = (Vec::new(), Vec::new());
+                read.encoder().encode_key(&mut key, 7, &doc).unwrap();
+                read.encoder()
+                    .encode_value(&mut value, 8, &doc, published_at.as_deref(), None)
+                    .unwrap();
+
+                assert_eq!(collection.encode_key(key_id, &doc).unwrap(), key);
+                assert_eq!(collection.encode_value(value_id, &doc).unwrap(), value);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_within_batch() {
+        use crate::topology::test::{collection_fixture, partition_fixture, spec_fixture};
+        use kafka_protocol::records::RecordBatchDecoder;
+
+        // Documents whose UUID clocks are out of offset order.
+        let docs = [
+            r#"{"id":"a","_meta":{"uuid":"9f2952f3-c6a3-11ea-8801-080607050309"}}"#,
+            r#"{"id":"b","_meta":{"uuid":"7b2952f3-c6a3-11ea-8801-080607050309"}}"#,
+            r#"{"id":"c","_meta":{"uuid":"8d2952f3-c6a3-11ea-8801-080607050309"}}"#,
+            r#"{"id":"d","_meta":{"uuid":"6a2952f3-c6a3-11ea-8801-080607050309"}}"#,
+        ];
+        let mut lines = String::new();
+        for doc in docs {
+            lines.extend([doc, "\n"]);
+        }
+        let collection = collection_fixture(spec_fixture());
+        let partition = partition_fixture("acmeCo/things/pivot=00");
+
+        // Read a batch from `offset`, returning the key and offset of each record.
+        let read_from = |offset: i64| {
+            let mut parser = simd_doc::Parser::new();
+            parser.chunk(lines.as_bytes(), 0).unwrap();
+            let transcoded = parser.transcode_many(Default::default()).unwrap();
+
+            let mut read = super::Read::new(
+                collection.journal_client.clone(),
+                &collection,
+                &partition,
+                offset,
+                std::sync::Arc::new(crate::topology::SchemaIds::new(7, 8)),
+            )
+            .unwrap();
+
+            // Serve documents from `offset` onward, as the broker would.
+            read.stream = futures::stream::iter(
+                transcoded
+                    .into_iter()
+                    .filter(move |(_root, next_offset)| *next_offset > offset)
+                    .map(|(root, next_offset)| ReadJsonLine::Doc { root, next_offset })
+                    .map(Ok),
+            )
+            .chain(futures::stream::pending())
+            .boxed();
+
+            async move {
+                let (_read, mut batch) = read.next_batch(1 << 20).await.unwrap();
+                RecordBatchDecoder::decode(&mut batch)
+                    .unwrap()
+                    .into_iter()
+                    .map(|record| (record.key.unwrap(), record.offset))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let all = read_from(0).await;
+        assert_eq!(all.len(), docs.len());
+
+        // Each record's offset is the last byte of its document.
+        let mut next_offset = 0;
+        for ((_key, offset), doc) in all.iter().zip(docs) {
+            next_offset += doc.len() as i64 + 1;
+            assert_eq!(*offset, next_offset - 1);
+        }
+
+        // A consumer which commits part-way through the batch resumes
+        // with exactly the records which follow its commit.
+        // (A read from the last offset would block awaiting a next document).
+        for (index, (_key, offset)) in all.iter().enumerate().take(docs.len() - 1) {
+            assert_eq!(read_from(offset + 1).await, all[index + 1..]);
+        }
+    }
+
+    #[test]
+    fn test_encode_json_key() {
+        let doc = json!({"id": "abc", "nested": {"n": 42}});
+        let key_ptr: Vec<_> = ["/id", "/nested/n", "/missing"]
+            .iter()
+            .map(|ptr| doc::Pointer::from_str(ptr))
+            .collect();
+
+        let mut b = Vec::new();
+        encode_json_key(&mut b, &doc, &key_ptr).unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&b).unwrap(),
+            json!(["abc", 42, null])
+        );
+    }
+
+    #[test]
+    fn test_publish_time_placement() {
+        let clock = gazette::uuid::Clock::from_unix(1_704_164_645, 678_000_000);
+        let at = Some("2024-01-02T03:04:05.678Z".to_string());
+
+        assert_eq!(
+            publish_time(TimestampPolicy::RecordTimestamp, clock),
+            (1_704_164_645_678, None)
+        );
+        assert_eq!(
+            publish_time(TimestampPolicy::ValueField, clock),
+            (-1, at.clone())
+        );
+        assert_eq!(
+            publish_time(TimestampPolicy::Both, clock),
+            (1_704_164_645_678, at)
+        );
+    }
+
+    #[test]
+    fn test_ingest_clock() {
+        let now = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_704_164_645_678);
+        let not_before = gazette::uuid::Clock::from_unix(1_704_000_000, 0);
+
+        // Documents of a persisted fragment are timestamped by its mod time.
+        let persisted = ingest_clock(1_704_100_000, now);
+        assert_eq!(persisted.to_unix(), (1_704_100_000, 0));
+        // Documents of an unpersisted fragment are timestamped by the current time.
+        let open = ingest_clock(0, now);
+        assert_eq!(open.to_unix(), (1_704_164_645, 678_000_000));
+
+        // Ingest clocks compare with `not_before`.
+        assert!(persisted >= not_before);
+        assert!(ingest_clock(1_703_999_999, now) < not_before);
+    }
+
+    #[test]
+    fn test_migrate_from_writer_schema() {
+        let writer = avro::Schema::parse(&json!({
+            "type": "record",
+            "name": "root",
+            "fields": [{"name": "a", "type": "string"}],
+        }))
+        .unwrap();
+        let reader = avro::Schema::parse(&json!({
+            "type": "record",
+            "name": "root",
+            "fields": [
+                {"name": "a", "type": "string"},
+                {"name": "b", "type": "long", "default": 42},
+                {"name": "c", "type": ["null", "string"], "default": null},
+            ],
+        }))
+        .unwrap();
+        let writer_schemas = vec![
+            WriterSchema {
+                registered_at: 100,
+                schema: writer.clone(),
+            },
+            WriterSchema {
+                registered_at: 200,
+                schema: reader.clone(),
+            },
+        ];
+
+        // Documents are migrated only from the writer schema current at their publish time.
+        let writer_schemas = WriterSchemas::new(&writer_schemas, &reader);
+        assert!(writer_schemas.select(50).is_none());
+        assert_eq!(
+            writer_schemas.select(100).map(|s| s.canonical_form()),
+            Some(writer.canonical_form())
+        );
+        assert!(writer_schemas.select(250).is_none());
+
+        // An old document takes the defaults of fields added since.
+        let mut migrated = Vec::new();
+        encode_migrated(&mut migrated, &writer, &reader, &json!({"a": "old"})).unwrap();
+
+        let mut expect = Vec::new();
+        avro::encode(
+            &mut expect,
+            &reader,
+            &json!({"a": "old", "b": 42, "c": null}),
+        )
+        .unwrap();
+        assert_eq!(migrated, expect);
+        assert_eq!(&migrated[4..], &[84, 0]); // Zig-zag 42, then the null variant.
+    }
 }
@@ -1,3 +1,4 @@
+use super::topology::{SubjectNameStrategy, SubjectSchema};
 use super::App;
 use anyhow::Context;
 use axum::{
@@ -5,89 +6,235 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use axum_extra::{headers, TypedHeader};
+use futures::StreamExt;
 use std::sync::Arc;
 
 // Build an axum::Router which implements a subset of the Confluent Schema Registry API,
-// sufficient for decoding Avro-encoded topic data.
+// sufficient for decoding topic data encoded with Avro or JSON schemas.
 pub fn build_router(app: Arc<App>) -> axum::Router<()> {
     use axum::routing::get;
 
     let schema_router = axum::Router::new()
         .route("/subjects", get(all_subjects))
+        .route("/subjects/:subject/versions", get(get_subject_versions))
         .route(
-            "/subjects/:subject/versions/latest",
-            get(get_subject_latest),
+            "/subjects/:subject/versions/:version",
+            get(get_subject_version),
         )
         .route("/schemas/ids/:id", get(get_schema_by_id))
+        .route("/topics/:topic/json-schema", get(get_topic_json_schema))
         .layer(tower_http::trace::TraceLayer::new_for_http())
         .with_state(app);
 
     schema_router
 }
 
-// List all collections as "subjects", which are generally Kafka topics in the ecosystem.
+// List the key and value "subjects" of all collections, as named by the App's
+// SubjectNameStrategy. Subjects are generally Kafka topics in the ecosystem, and
+// are those of `<topic>-key` and `<topic>-value` under the default TopicName strategy.
+// Record-based strategies name the records of collections, which are fetched to list
+// them, and collections which fail to fetch are logged and skipped.
 #[tracing::instrument(skip_all)]
 async fn all_subjects(
     axum::extract::State(app): axum::extract::State<Arc<App>>,
     TypedHeader(auth): TypedHeader<headers::Authorization<headers::authorization::Basic>>,
 ) -> Response {
     wrap(async move {
-        let client = app.authenticate(auth.username(), auth.password()).await?;
+        let (client, options) = app.authenticate(auth.username(), auth.password()).await?;
+        let strategy = app.subject_name_strategy;
 
-        super::fetch_all_collection_names(&client)
+        let names = super::topology::TopicNames::fetch(&client, app.topic_name_mapper.as_ref())
             .await
-            .context("failed to list collections from the control plane")
+            .context("failed to list collections from the control plane")?;
+
+        if strategy == SubjectNameStrategy::TopicName {
+            return Ok(names
+                .iter()
+                .flat_map(|(topic, _)| {
+                    [
+                        strategy.subject(topic, true, None),
+                        strategy.subject(topic, false, None),
+                    ]
+                })
+                .collect::<Vec<_>>());
+        }
+
+        let names: Vec<(String, String)> = names
+            .iter()
+            .map(|(topic, catalog_name)| (topic.to_string(), catalog_name.to_string()))
+            .collect();
+
+        let (client, options) = (&client, &options);
+        let subjects: Vec<Vec<String>> = futures::stream::iter(names)
+            .map(|(topic, catalog_name)| async move {
+                match super::Collection::new_shared(client, &catalog_name, options.clone()).await {
+                    Ok(Some(collection)) => {
+                        let (key_schema, value_schema) = &collection.registry_schemas;
+                        vec![
+                            strategy.subject(&topic, true, key_schema.record_name().as_deref()),
+                            strategy.subject(&topic, false, value_schema.record_name().as_deref()),
+                        ]
+                    }
+                    Ok(None) => Vec::new(),
+                    Err(err) => {
+                        tracing::warn!(topic, ?err, "skipping subjects of a collection");
+                        Vec::new()
+                    }
+                }
+            })
+            .buffered(SUBJECT_COLLECTION_CONCURRENCY)
+            .collect()
+            .await;
+
+        Ok(subjects.into_iter().flatten().collect())
     })
     .await
 }
 
-// Fetch the "latest" schema for a subject (collection).
+// Number of collections which are concurrently fetched to list their subjects.
+const SUBJECT_COLLECTION_CONCURRENCY: usize = 16;
+
+// List the versions of a subject (collection), which number the schemas registered
+// for it from version 1. A subject having no registered schemas lists version 1,
+// which is registered on its first fetch.
 #[tracing::instrument(skip(app, auth))]
-async fn get_subject_latest(
+async fn get_subject_versions(
     axum::extract::State(app): axum::extract::State<Arc<App>>,
     TypedHeader(auth): TypedHeader<headers::Authorization<headers::authorization::Basic>>,
     axum::extract::Path(subject): axum::extract::Path<String>,
 ) -> Response {
     wrap(async move {
-        let client = app.authenticate(auth.username(), auth.password()).await?;
+        let (client, options) = app.authenticate(auth.username(), auth.password()).await?;
+        let (topic, schema) = parse_subject(&app, &subject)?;
+        let collection = subject_collection(&app, &client, &options, topic).await?;
+        let is_key = subject_is_key(&collection, &subject, topic, schema)?;
 
-        let (is_key, collection) = if subject.ends_with("-value") {
-            (false, &subject[..subject.len() - 6])
-        } else if subject.ends_with("-key") {
-            (true, &subject[..subject.len() - 4])
-        } else {
-            anyhow::bail!("expected subject to end with -key or -value")
-        };
-
-        let collection = super::Collection::new(&client, collection)
+        let versions = collection
+            .fetch_subject_versions(&client, is_key)
             .await
-            .context("failed to fetch collection metadata")?
-            .with_context(|| format!("collection {collection} does not exist"))?;
+            .context("failed to fetch the registered schemas of the subject")?;
+
+        Ok((1..=versions.len().max(1)).collect::<Vec<_>>())
+    })
+    .await
+}
+
+// Fetch the "latest" schema for a subject (collection), or a numbered version of it.
+// The latest schema is that of the collection's current spec, and is registered if it
+// isn't already.
+#[tracing::instrument(skip(app, auth))]
+async fn get_subject_version(
+    axum::extract::State(app): axum::extract::State<Arc<App>>,
+    TypedHeader(auth): TypedHeader<headers::Authorization<headers::authorization::Basic>>,
+    axum::extract::Path((subject, version)): axum::extract::Path<(String, String)>,
+) -> Response {
+    wrap(async move {
+        let (client, options) = app.authenticate(auth.username(), auth.password()).await?;
+        let (topic, schema) = parse_subject(&app, &subject)?;
+        let collection = subject_collection(&app, &client, &options, topic).await?;
+        let is_key = subject_is_key(&collection, &subject, topic, schema)?;
+
+        if version != "latest" {
+            let versions = collection
+                .fetch_subject_versions(&client, is_key)
+                .await
+                .context("failed to fetch the registered schemas of the subject")?;
+
+            let number: usize = version.parse().unwrap_or(0);
+            let Some((id, schema)) = number.checked_sub(1).and_then(|index| versions.get(index))
+            else {
+                anyhow::bail!("version {version} of subject {subject} not found");
+            };
+
+            return Ok(serde_json::json!({
+                "id": id,
+                "schema": schema.to_string(),
+                "schemaType": collection.options.schema_format.schema_type(),
+                "subject": subject,
+                "version": number,
+            }));
+        }
 
         let (key_id, value_id) = collection
             .registered_schema_ids(&client)
             .await
-            .context("failed to resolve registered Avro schemas")?;
+            .context("failed to resolve registered schemas")?;
 
+        let (key_schema, value_schema) = &collection.registry_schemas;
         let (id, schema) = if is_key {
-            (key_id, &collection.key_schema)
+            (key_id, key_schema)
         } else {
-            (value_id, &collection.value_schema)
+            (value_id, value_schema)
         };
 
+        // The latest schema is usually the last registered version,
+        // unless the current spec reverted to an earlier schema.
+        let versions = collection
+            .fetch_subject_versions(&client, is_key)
+            .await
+            .context("failed to fetch the registered schemas of the subject")?;
+        let version = versions
+            .iter()
+            .position(|(registry_id, _)| *registry_id == id.id)
+            .map_or(versions.len().max(1), |index| index + 1);
+
         Ok(serde_json::json!({
-            "id": id,
-            "schema": schema.canonical_form(),
-            "schemaType": "AVRO",
+            "id": id.id,
+            "schema": schema.registry_form(),
+            "schemaType": id.format.schema_type(),
             "subject": subject,
-            "version": 1,
+            "version": version,
         }))
     })
     .await
 }
 
+// Resolve the topic of a subject into its readable Collection.
+async fn subject_collection(
+    app: &App,
+    client: &postgrest::Postgrest,
+    options: &super::topology::Options,
+    topic: &str,
+) -> anyhow::Result<Arc<super::Collection>> {
+    let collection = super::topology::resolve_topic(client, app.topic_name_mapper.as_ref(), topic)
+        .await
+        .context("failed to resolve the collection of the subject")?
+        .ok_or_else(|| super::topology::TopologyError::CollectionNotFound {
+            collection: topic.to_string(),
+        })?;
+
+    let collection = super::Collection::new_shared(client, &collection, options.clone())
+        .await
+        .context("failed to fetch collection metadata")?
+        .ok_or_else(|| super::topology::TopologyError::CollectionNotFound {
+            collection: collection.to_string(),
+        })?;
+
+    Ok(collection)
+}
+
+// Is the `schema` of `subject` the key schema of `collection`, rather than its value schema?
+fn subject_is_key(
+    collection: &super::Collection,
+    subject: &str,
+    topic: &str,
+    schema: SubjectSchema,
+) -> anyhow::Result<bool> {
+    let (key_schema, value_schema) = &collection.registry_schemas;
+
+    Ok(match schema {
+        SubjectSchema::Key => true,
+        SubjectSchema::Value => false,
+        SubjectSchema::Record(name) if value_schema.record_name().as_deref() == Some(name) => false,
+        SubjectSchema::Record(name) if key_schema.record_name().as_deref() == Some(name) => true,
+        SubjectSchema::Record(name) => {
+            anyhow::bail!("subject {subject} names record {name}, which isn't a schema of {topic}")
+        }
+    })
+}
+
 // Fetch the schema with the given ID.
-// Schemas are content-addressed and immutable, so an ID uniquely identifies a Avro schema.
+// Schemas are content-addressed and immutable, so an ID uniquely identifies a schema.
 #[tracing::instrument(skip(app, auth))]
 async fn get_schema_by_id(
     axum::extract::State(app): axum::extract::State<Arc<App>>,
@@ -95,41 +242,72 @@ async fn get_schema_by_id(
     axum::extract::Path(id): axum::extract::Path<u32>,
 ) -> Response {
     wrap(async move {
-        let client = app.authenticate(auth.username(), auth.password()).await?;
+        let (client, _options) = app.authenticate(auth.username(), auth.password()).await?;
+
+        let Some((schema_type, avro_schema)) =
+            super::topology::fetch_registered_schema(&client, id).await?
+        else {
+            anyhow::bail!("could not find schema with registry id {id}");
+        };
 
-        #[derive(serde::Deserialize)]
-        struct Row {
-            avro_schema: serde_json::Value,
+        // Confluent's registry omits the `schemaType` of Avro schemas.
+        if schema_type == "AVRO" {
+            Ok(serde_json::json!({
+                "schema": avro_schema.to_string(),
+            }))
+        } else {
+            Ok(serde_json::json!({
+                "schema": avro_schema.to_string(),
+                "schemaType": schema_type,
+            }))
         }
+    })
+    .await
+}
 
-        let now = time::OffsetDateTime::now_utc();
-        let now = now
-            .format(&time::format_description::well_known::Rfc3339)
-            .unwrap();
-
-        let mut rows: Vec<Row> = client
-            .from("registered_avro_schemas")
-            .eq("registry_id", format!("{id}"))
-            .update(serde_json::json!({"updated_at": now}).to_string())
-            .select("avro_schema")
-            .execute()
-            .await
-            .and_then(|r| r.error_for_status())
-            .context("querying for an already-registered schema")?
-            .json()
-            .await?;
+// Fetch the JSON schema of a topic's collection, from which its registered schemas are derived.
+// It's not part of the Confluent API, but lets tooling validate documents as dekaf does.
+#[tracing::instrument(skip(app, auth))]
+async fn get_topic_json_schema(
+    axum::extract::State(app): axum::extract::State<Arc<App>>,
+    TypedHeader(auth): TypedHeader<headers::Authorization<headers::authorization::Basic>>,
+    axum::extract::Path(topic): axum::extract::Path<String>,
+) -> Response {
+    wrap(async move {
+        let (client, options) = app.authenticate(auth.username(), auth.password()).await?;
 
-        let Some(Row { avro_schema }) = rows.pop() else {
-            anyhow::bail!("could not find schema with registry id {id}");
-        };
+        let collection =
+            super::topology::resolve_topic(&client, app.topic_name_mapper.as_ref(), &topic)
+                .await
+                .context("failed to resolve the collection of the topic")?
+                .ok_or_else(|| super::topology::TopologyError::CollectionNotFound {
+                    collection: topic.to_string(),
+                })?;
 
-        Ok(serde_json::json!({
-            "schema": avro_schema.to_string(),
-        }))
+        let collection = super::Collection::new_shared(&client, &collection, options)
+            .await
+            .context("failed to fetch collection metadata")?
+            .ok_or_else(|| super::topology::TopologyError::CollectionNotFound {
+                collection: collection.to_string(),
+            })?;
+
+        serde_json::from_str::<serde_json::Value>(collection.json_schema())
+            .context("failed to parse the collection's JSON schema")
     })
     .await
 }
 
+// Split a subject into its topic and the schema of it which the subject identifies,
+// per the App's SubjectNameStrategy. A subject must name its topic to be served.
+fn parse_subject<'s>(app: &App, subject: &'s str) -> anyhow::Result<(&'s str, SubjectSchema<'s>)> {
+    match app.subject_name_strategy.parse_subject(subject)? {
+        (Some(topic), schema) => Ok((topic, schema)),
+        (None, _) => {
+            anyhow::bail!("subject {subject} names only a record, and can't be resolved to a topic")
+        }
+    }
+}
+
 async fn wrap<F, T>(fut: F) -> Response
 where
     T: serde::Serialize,
@@ -1,4 +1,10 @@
-use super::{fetch_all_collection_names, App, Collection, Read};
+use super::{
+    topology::{
+        kafka_error, resolve_collection, validate_topic_name, Offset, Options, PartitionIndex,
+        SchemaIds, TopicNames,
+    },
+    App, Collection, Read,
+};
 use anyhow::Context;
 use kafka_protocol::{
     error::ResponseError,
@@ -18,19 +24,98 @@ struct PendingRead {
 pub struct Session {
     app: Arc<App>,
     client: postgrest::Postgrest,
+    // Options of the Collections built by the session's client.
+    options: Options,
     reads: HashMap<(TopicName, i32), PendingRead>,
+    // Topic names of the session's readable collections, if they've been fetched.
+    topic_names: Option<TopicNames>,
+    // Topics which have been resolved into the catalog names of readable collections.
+    resolved_topics: HashMap<String, String>,
 }
 
 impl Session {
     pub fn new(app: Arc<App>) -> Self {
         let client = app.anon_client.clone();
+        let options = app.collection_options.clone();
         Self {
             app,
             client,
+            options,
             reads: HashMap::new(),
+            topic_names: None,
+            resolved_topics: HashMap::new(),
         }
     }
 
+    /// Map Kafka `topics` into the catalog names of their collections through the
+    /// App's TopicNameMapper, or None for each topic which no readable collection
+    /// maps into. Topics of the Identity mapper are their catalog names. Others which
+    /// the mapper inverts are checked concurrently with `resolve_collection`, and the
+    /// rest are resolved through the session's TopicNames, which are re-fetched (at
+    /// most once per call) if they're unset or don't include a topic.
+    /// Resolved topics are remembered by the session.
+    async fn catalog_names<'t>(
+        &mut self,
+        topics: impl IntoIterator<Item = &'t str>,
+    ) -> anyhow::Result<Vec<Option<String>>> {
+        let mapper = self.app.topic_name_mapper.as_ref();
+        let topics: Vec<&str> = topics.into_iter().collect();
+
+        if mapper.is_identity() {
+            return Ok(topics
+                .into_iter()
+                .map(|topic| Some(topic.to_string()))
+                .collect());
+        }
+        let (client, resolved_topics) = (&self.client, &self.resolved_topics);
+        let inverted: Vec<Option<String>> = topics
+            .iter()
+            .map(|topic| mapper.catalog_name(topic))
+            .collect();
+
+        let mut resolved: Vec<Option<String>> =
+            futures::future::try_join_all(topics.iter().zip(&inverted).map(
+                |(topic, inverted)| async move {
+                    if let Some(catalog_name) = resolved_topics.get(*topic) {
+                        return Ok(Some(catalog_name.clone()));
+                    }
+                    match inverted {
+                        Some(catalog_name) => resolve_collection(client, catalog_name).await,
+                        None => Ok(None), // Resolved through TopicNames below.
+                    }
+                },
+            ))
+            .await?;
+
+        let mut fetched = false;
+        for ((topic, inverted), resolved) in topics.iter().zip(&inverted).zip(&mut resolved) {
+            if resolved.is_some() || inverted.is_some() {
+                continue;
+            }
+            let cached = |names: &Option<TopicNames>| {
+                names
+                    .as_ref()
+                    .and_then(|names| names.catalog_name(topic))
+                    .map(str::to_string)
+            };
+            *resolved = cached(&self.topic_names);
+
+            if resolved.is_none() && !fetched {
+                self.topic_names = Some(TopicNames::fetch(&self.client, mapper).await?);
+                fetched = true;
+                *resolved = cached(&self.topic_names);
+            }
+        }
+
+        for (topic, resolved) in topics.iter().zip(&resolved) {
+            if let Some(catalog_name) = resolved {
+                self.resolved_topics
+                    .insert(topic.to_string(), catalog_name.clone());
+            }
+        }
+        Ok(resolved)
+    }
+
     /// SASL handshake responds with supported SASL mechanisms.
     /// We support PLAIN user/password, because we expect the password to be a control-plane token.
     pub async fn sasl_handshake(
@@ -61,8 +146,12 @@ impl Session {
         let password = it.next().context("expected SASL passwd")??;
 
         let response = match self.app.authenticate(authcid, password).await {
-            Ok(client) => {
+            Ok((client, options)) => {
                 self.client = client;
+                self.options = options;
+                // Readable collections are of the new client.
+                self.topic_names = None;
+                self.resolved_topics.clear();
 
                 let mut response = messages::SaslAuthenticateResponse::default();
                 response.session_lifetime_ms = i64::MAX; // TODO(johnny): Access token expiry.
@@ -117,15 +206,26 @@ impl Session {
     async fn metadata_all_topics(
         &mut self,
     ) -> anyhow::Result<IndexMap<TopicName, MetadataResponseTopic>> {
-        let collections = fetch_all_collection_names(&self.client).await?;
+        let names = TopicNames::fetch(&self.client, self.app.topic_name_mapper.as_ref()).await?;
 
-        tracing::debug!(collections=?ops::DebugJson(&collections), "fetched all collections");
+        tracing::debug!(
+            collections = ?ops::DebugJson(names.iter().map(|(_, c)| c).collect::<Vec<_>>()),
+            "fetched all collections"
+        );
 
-        let topics = collections
-            .into_iter()
-            .map(|name| {
+        let validate = self.app.validate_topic_names;
+        let topics = names
+            .iter()
+            .filter(|(topic, _)| match validate_topic_name(topic) {
+                Err(err) if validate => {
+                    tracing::warn!(%err, "omitting collection having an invalid topic name");
+                    false
+                }
+                _ => true,
+            })
+            .map(|(topic, _)| {
                 (
-                    TopicName(StrBytes::from_string(name)),
+                    TopicName(StrBytes::from_string(topic.to_string())),
                     MetadataResponseTopic::builder()
                         .is_internal(false)
                         .build()
@@ -134,6 +234,7 @@ impl Session {
             })
             .collect();
 
+        self.topic_names = Some(names);
         Ok(topics)
     }
 
@@ -142,13 +243,34 @@ impl Session {
         &mut self,
         requests: Vec<messages::metadata_request::MetadataRequestTopic>,
     ) -> anyhow::Result<IndexMap<TopicName, MetadataResponseTopic>> {
-        let client = &self.client;
+        let names: Vec<TopicName> = requests
+            .into_iter()
+            .map(|topic| topic.name.unwrap_or_default())
+            .collect();
+        let catalog_names = self
+            .catalog_names(names.iter().map(|name| name.as_str()))
+            .await?;
+        let resolved = names.into_iter().zip(catalog_names);
+        let (client, options) = (&self.client, &self.options);
 
         // Concurrently fetch Collection instances for all requested topics.
-        let collections: anyhow::Result<Vec<(TopicName, Option<Collection>)>> =
-            futures::future::try_join_all(requests.into_iter().map(|topic| async move {
-                let name = topic.name.unwrap_or_default();
-                let maybe_collection = Collection::new(client, name.as_str()).await?;
+        // Failures having a Kafka error are that topic's error, and others fail the request.
+        let collections: anyhow::Result<Vec<(TopicName, Result<Arc<Collection>, ResponseError>)>> =
+            futures::future::try_join_all(resolved.into_iter().map(|(name, catalog_name)| async move {
+                let Some(catalog_name) = catalog_name else {
+                    return Ok((name, Err(ResponseError::UnknownTopicOrPartition)));
+                };
+                let maybe_collection = match Collection::new_shared(client, &catalog_name, options.clone()).await {
+                    Ok(Some(collection)) => Ok(collection),
+                    Ok(None) => Err(ResponseError::UnknownTopicOrPartition),
+                    Err(err) => match kafka_error(&err) {
+                        Some(code) => {
+                            tracing::warn!(topic = ?name, ?err, "failed to fetch topic metadata");
+                            Err(code)
+                        }
+                        None => return Err(err),
+                    },
+                };
                 Ok((name, maybe_collection))
             }))
             .await;
@@ -156,37 +278,25 @@ impl Session {
         let mut topics = IndexMap::new();
 
         for (name, maybe_collection) in collections? {
-            let Some(collection) = maybe_collection else {
-                topics.insert(
-                    name,
-                    MetadataResponseTopic::builder()
-                        .error_code(ResponseError::UnknownTopicOrPartition.code())
-                        .build()
-                        .unwrap(),
-                );
-                continue;
+            let collection = match maybe_collection {
+                Ok(collection) => collection,
+                Err(code) => {
+                    topics.insert(
+                        name,
+                        MetadataResponseTopic::builder()
+                            .error_code(code.code())
+                            .build()
+                            .unwrap(),
+                    );
+                    continue;
+                }
             };
 
-            let partitions = collection
-                .partitions
-                .iter()
-                .enumerate()
-                .map(|(index, _)| {
-                    messages::metadata_response::MetadataResponsePartition::builder()
-                        .partition_index(index as i32)
-                        .leader_id(messages::BrokerId(1))
-                        .replica_nodes(vec![messages::BrokerId(1)])
-                        .isr_nodes(vec![messages::BrokerId(1)])
-                        .build()
-                        .unwrap()
-                })
-                .collect();
-
             topics.insert(
                 name,
                 MetadataResponseTopic::builder()
                     .is_internal(false)
-                    .partitions(partitions)
+                    .partitions(metadata_partitions(&collection))
                     .build()
                     .unwrap(),
             );
@@ -226,44 +336,49 @@ impl Session {
         &mut self,
         request: messages::ListOffsetsRequest,
     ) -> anyhow::Result<messages::ListOffsetsResponse> {
-        let client = &self.client;
+        let catalog_names = self
+            .catalog_names(request.topics.iter().map(|topic| topic.name.as_str()))
+            .await?;
+        let (client, options) = (&self.client, &self.options);
 
         // Concurrently fetch Collection instances and offsets for all requested topics and partitions.
         // Map each "topic" into Vec<(Partition Index, Option<(Journal Offset, Timestamp))>.
-        let collections: anyhow::Result<Vec<(TopicName, Vec<(i32, Option<(i64, i64)>)>)>> =
-            futures::future::try_join_all(request.topics.into_iter().map(|topic| async move {
-                let maybe_collection = Collection::new(client, topic.name.as_str()).await?;
-
-                let Some(collection) = maybe_collection else {
-                    return Ok((
-                        topic.name,
-                        topic
-                            .partitions
-                            .iter()
-                            .map(|p| (p.partition_index, None))
-                            .collect(),
-                    ));
-                };
-                let collection = &collection;
-
-                // Concurrently fetch requested offset for each named partition.
-                let offsets: anyhow::Result<_> = futures::future::try_join_all(
-                    topic.partitions.into_iter().map(|partition| async move {
-                        Ok((
-                            partition.partition_index,
-                            collection
-                                .fetch_partition_offset(
-                                    partition.partition_index as usize,
-                                    partition.timestamp, // In millis.
-                                )
-                                .await?,
-                        ))
-                    }),
-                )
-                .await;
-
-                Ok((topic.name, offsets?))
-            }))
+        let collections: anyhow::Result<Vec<(TopicName, Vec<(i32, Option<(Offset, i64)>)>)>> =
+            futures::future::try_join_all(request.topics.into_iter().zip(catalog_names).map(
+                |(topic, catalog_name)| async move {
+                    let maybe_collection = match catalog_name {
+                        Some(catalog_name) => {
+                            Collection::new_shared(client, &catalog_name, options.clone()).await?
+                        }
+                        None => None,
+                    };
+
+                    let Some(collection) = maybe_collection else {
+                        return Ok((
+                            topic.name,
+                            topic
+                                .partitions
+                                .iter()
+                                .map(|p| (p.partition_index, None))
+                                .collect(),
+                        ));
+                    };
+                    let collection = &collection;
+
+                    // Concurrently fetch requested offset for each named partition.
+                    let requests = journal_offset_requests(collection, &topic.partitions);
+                    let offsets = collection.fetch_partition_offsets(&requests).await?;
+
+                    let offsets = topic
+                        .partitions
+                        .iter()
+                        .map(|p| p.partition_index)
+                        .zip(offsets)
+                        .collect();
+
+                    Ok((topic.name, offsets))
+                },
+            ))
             .await;
 
         use messages::list_offsets_response::{
@@ -277,7 +392,7 @@ impl Session {
                 let partitions = offsets
                     .into_iter()
                     .map(|(partition_index, maybe_offset)| {
-                        let Some((offset, timestamp)) = maybe_offset else {
+                        let Some((Offset(offset), timestamp)) = maybe_offset else {
                             return ListOffsetsPartitionResponse::builder()
                                 .partition_index(partition_index)
                                 .error_code(ResponseError::UnknownTopicOrPartition.code())
@@ -324,6 +439,18 @@ impl Session {
             ..
         } = request;
 
+        let mut topics: Vec<&TopicName> = Vec::with_capacity(topic_requests.len());
+        for topic_request in &topic_requests {
+            if !topics.contains(&&topic_request.topic) {
+                topics.push(&topic_request.topic);
+            }
+        }
+        let catalog_names = self
+            .catalog_names(topics.iter().map(|topic| topic.as_str()))
+            .await?;
+        let catalog_names: HashMap<TopicName, Option<String>> =
+            topics.into_iter().cloned().zip(catalog_names).collect();
+
         let client = &self.client;
         let timeout = tokio::time::sleep(std::time::Duration::from_millis(max_wait_ms as u64));
         let timeout = futures::future::maybe_done(timeout);
@@ -340,26 +467,35 @@ impl Session {
                 {
                     continue; // Common case: fetch is at the pending offset.
                 }
-                let Some(collection) = Collection::new(client, &key.0).await? else {
+                let Some(catalog_name) = &catalog_names[&key.0] else {
+                    continue; // Topic isn't of a collection.
+                };
+                let Some(collection) =
+                    Collection::new_shared(client, catalog_name, self.options.clone()).await?
+                else {
                     continue; // Collection doesn't exist.
                 };
-                let Some(partition) = collection
-                    .partitions
-                    .get(partition_request.partition as usize)
+                let Some((partition, shard)) =
+                    collection.resolve_kafka_partition(partition_request.partition as usize)
                 else {
                     continue; // Partition doesn't exist.
                 };
-                let (key_schema_id, value_schema_id) =
-                    collection.registered_schema_ids(&client).await?;
+                let (key_id, value_id) = collection.registered_schema_ids(&client).await?;
+                let (key_schema_id, value_schema_id) = (key_id.id, value_id.id);
+                let schema_ids = Arc::new(SchemaIds::new(key_schema_id, value_schema_id));
+
+                if let Some(interval) = collection.options.schema_id_revalidation_interval {
+                    collection.spawn_schema_id_revalidation(client.clone(), &schema_ids, interval);
+                }
 
                 let read = Read::new(
                     collection.journal_client.clone(),
                     &collection,
                     partition,
                     partition_request.fetch_offset,
-                    key_schema_id,
-                    value_schema_id,
-                );
+                    schema_ids,
+                )?
+                .with_fan_out_shard(&collection, shard);
                 let pending = PendingRead {
                     offset: partition_request.fetch_offset,
                     last_write_head: partition_request.fetch_offset,
@@ -591,3 +727,174 @@ impl Session {
         Ok(res)
     }
 }
+
+/// Map each Kafka partition of `collection` into its metadata.
+fn metadata_partitions(
+    collection: &Collection,
+) -> Vec<messages::metadata_response::MetadataResponsePartition> {
+    (0..collection.kafka_partition_count())
+        .map(|index| {
+            messages::metadata_response::MetadataResponsePartition::builder()
+                .partition_index(index as i32)
+                .leader_id(messages::BrokerId(1))
+                .replica_nodes(vec![messages::BrokerId(1)])
+                .isr_nodes(vec![messages::BrokerId(1)])
+                .build()
+                .unwrap()
+        })
+        .collect()
+}
+
+/// Map requested Kafka partitions and timestamps into requests of offsets of their journals.
+/// The shards of a PartitionFanOut are offsets of their journal, which they share.
+fn journal_offset_requests(
+    collection: &Collection,
+    partitions: &[messages::list_offsets_request::ListOffsetsPartition],
+) -> Vec<(PartitionIndex, i64)> {
+    partitions
+        .iter()
+        .map(|p| {
+            let (index, _shard) = collection.kafka_partition_journal(p.partition_index as usize);
+            (index, p.timestamp) // In millis.
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        journal_offset_requests, metadata_partitions, App, Collection, PartitionIndex, Session,
+    };
+    use crate::topology::{
+        test::{collection_fixture, fake_postgrest, partition_fixture, spec_fixture},
+        Identity, PartitionFanOut, ReplaceSlashes, TopicNameMapper,
+    };
+    use kafka_protocol::{messages::list_offsets_request::ListOffsetsPartition, protocol::Builder};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn session_fixture(
+        client: postgrest::Postgrest,
+        topic_name_mapper: Arc<dyn TopicNameMapper>,
+    ) -> Session {
+        Session::new(Arc::new(App {
+            anon_client: client,
+            advertise_host: "localhost".to_string(),
+            advertise_kafka_port: 9092,
+            validate_topic_names: false,
+            topic_name_mapper,
+            subject_name_strategy: Default::default(),
+            collection_options: Default::default(),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_catalog_names() {
+        use axum::routing::get;
+
+        // A fake PostgREST which counts its listings of collection names.
+        let listings = Arc::new(AtomicUsize::new(0));
+        let listings_clone = listings.clone();
+
+        let router = axum::Router::new().route(
+            "/live_specs_ext",
+            get(move || {
+                listings_clone.fetch_add(1, Ordering::SeqCst);
+                async {
+                    serde_json::json!([{"catalog_name": "acmeCo/a"}, {"catalog_name": "acmeCo/b"}])
+                        .to_string()
+                }
+            }),
+        );
+        let client = fake_postgrest(router).await;
+        let mut session = session_fixture(client.clone(), Arc::new(ReplaceSlashes('_')));
+
+        // Topic names are listed once for all topics of a request.
+        let resolved = session
+            .catalog_names(["acmeCo_a", "acmeCo_missing", "acmeCo_b"])
+            .await
+            .unwrap();
+        assert_eq!(
+            resolved,
+            [
+                Some("acmeCo/a".to_string()),
+                None,
+                Some("acmeCo/b".to_string())
+            ]
+        );
+        assert_eq!(listings.load(Ordering::SeqCst), 1);
+
+        // Resolved topics are remembered, and topics which weren't are re-listed.
+        session.catalog_names(["acmeCo_a"]).await.unwrap();
+        assert_eq!(listings.load(Ordering::SeqCst), 1);
+        session
+            .catalog_names(["acmeCo_missing", "acmeCo_other"])
+            .await
+            .unwrap();
+        assert_eq!(listings.load(Ordering::SeqCst), 2);
+
+        // Topics of the Identity mapper aren't resolved at all.
+        let mut session = session_fixture(client, Arc::new(Identity));
+        let resolved = session.catalog_names(["acmeCo/missing"]).await.unwrap();
+        assert_eq!(resolved, [Some("acmeCo/missing".to_string())]);
+        assert_eq!(listings.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_partition_fan_out_session() {
+        let mut collection = collection_fixture(spec_fixture());
+        collection.partitions = vec![
+            partition_fixture("acmeCo/things/pivot=00"),
+            partition_fixture("acmeCo/things/pivot=80"),
+        ];
+
+        fn indices(collection: &Collection) -> Vec<i32> {
+            metadata_partitions(collection)
+                .iter()
+                .map(|p| p.partition_index)
+                .collect()
+        }
+        fn requests(collection: &Collection, partitions: &[i32]) -> Vec<(PartitionIndex, i64)> {
+            let partitions: Vec<_> = partitions
+                .iter()
+                .map(|index| {
+                    ListOffsetsPartition::builder()
+                        .partition_index(*index)
+                        .timestamp(-1)
+                        .build()
+                        .unwrap()
+                })
+                .collect();
+            journal_offset_requests(collection, &partitions)
+        }
+
+        // Without a PartitionFanOut, each journal is a Kafka partition.
+        assert_eq!(indices(&collection), vec![0, 1]);
+        assert_eq!(
+            requests(&collection, &[1, 2]),
+            vec![(PartitionIndex(1), -1), (PartitionIndex(2), -1)],
+        );
+
+        // With one, each journal is `shards` Kafka partitions which read
+        // from its offsets, and partitions beyond the last shard don't exist.
+        collection.options.partition_fan_out = Some(PartitionFanOut::new(3, 1024).unwrap());
+
+        assert_eq!(indices(&collection), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(
+            requests(&collection, &[0, 2, 4, 6]),
+            vec![
+                (PartitionIndex(0), -1),
+                (PartitionIndex(0), -1),
+                (PartitionIndex(1), -1),
+                (PartitionIndex(2), -1),
+            ],
+        );
+
+        let (partition, shard) = collection.resolve_kafka_partition(4).unwrap();
+        assert_eq!(
+            (partition.spec.name.as_str(), shard),
+            ("acmeCo/things/pivot=80", 1)
+        );
+        assert!(collection.resolve_kafka_partition(6).is_none());
+    }
+}
@@ -0,0 +1,228 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Outcome of a request recorded by a TelemetrySink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+/// Kind of a registered schema, as the key or value schema of a collection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaKind {
+    Key,
+    Value,
+}
+
+impl SchemaKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Key => "key",
+            Self::Value => "value",
+        }
+    }
+}
+
+/// TelemetrySink records the latency and outcome of control-plane and
+/// data-plane requests made by topology operations. Embedders may install
+/// their own implementation (for example, one backed by OpenTelemetry)
+/// using `set_telemetry_sink`.
+pub trait TelemetrySink: Send + Sync {
+    /// Record a completed request of `operation`, which took `latency`.
+    fn record_request(&self, operation: &'static str, latency: Duration, outcome: Outcome);
+
+    /// Record a lookup of an already-registered schema of `kind`, which took `latency`
+    /// and either found the schema (`hit`) or didn't, meaning it must be registered.
+    fn record_schema_lookup(&self, _kind: SchemaKind, _hit: bool, _latency: Duration) {}
+}
+
+/// NoopSink discards all telemetry, and is the default sink.
+pub struct NoopSink;
+
+impl TelemetrySink for NoopSink {
+    fn record_request(&self, _operation: &'static str, _latency: Duration, _outcome: Outcome) {}
+}
+
+/// MetricsSink records telemetry through the `metrics` facade,
+/// as a latency histogram and a request counter labeled by operation and outcome.
+#[cfg(feature = "metrics")]
+pub struct MetricsSink;
+
+#[cfg(feature = "metrics")]
+impl TelemetrySink for MetricsSink {
+    fn record_request(&self, operation: &'static str, latency: Duration, outcome: Outcome) {
+        let outcome = outcome.as_str();
+
+        metrics::histogram!(
+            "dekaf_request_latency_seconds",
+            "operation" => operation,
+            "outcome" => outcome
+        )
+        .record(latency.as_secs_f64());
+
+        metrics::counter!(
+            "dekaf_requests_total",
+            "operation" => operation,
+            "outcome" => outcome
+        )
+        .increment(1);
+    }
+
+    fn record_schema_lookup(&self, kind: SchemaKind, hit: bool, latency: Duration) {
+        let (kind, result) = (kind.as_str(), if hit { "hit" } else { "miss" });
+
+        metrics::histogram!(
+            "dekaf_schema_registry_lookup_latency_seconds",
+            "kind" => kind,
+            "result" => result
+        )
+        .record(latency.as_secs_f64());
+
+        metrics::counter!(
+            "dekaf_schema_registry_lookups_total",
+            "kind" => kind,
+            "result" => result
+        )
+        .increment(1);
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref TELEMETRY_SINK: RwLock<Arc<dyn TelemetrySink>> = RwLock::new(Arc::new(NoopSink));
+}
+
+/// Install the TelemetrySink to which all further requests are recorded.
+pub fn set_telemetry_sink(sink: Arc<dyn TelemetrySink>) {
+    *TELEMETRY_SINK.write().unwrap() = sink;
+}
+
+/// Await the request future `fut` of `operation`, recording it to the installed sink.
+pub(crate) async fn observe<T, E, F>(operation: &'static str, fut: F) -> Result<T, E>
+where
+    F: std::future::Future<Output = Result<T, E>>,
+{
+    let sink = TELEMETRY_SINK.read().unwrap().clone();
+    observe_with(sink.as_ref(), operation, fut).await
+}
+
+/// Await the schema lookup future `fut` of `kind`, recording whether it found
+/// a registered schema to the installed sink. Failed lookups aren't recorded.
+pub(crate) async fn observe_schema_lookup<T, E, F>(kind: SchemaKind, fut: F) -> Result<Option<T>, E>
+where
+    F: std::future::Future<Output = Result<Option<T>, E>>,
+{
+    let sink = TELEMETRY_SINK.read().unwrap().clone();
+    observe_schema_lookup_with(sink.as_ref(), kind, fut).await
+}
+
+async fn observe_schema_lookup_with<T, E, F>(
+    sink: &dyn TelemetrySink,
+    kind: SchemaKind,
+    fut: F,
+) -> Result<Option<T>, E>
+where
+    F: std::future::Future<Output = Result<Option<T>, E>>,
+{
+    let start = Instant::now();
+    let result = fut.await;
+
+    if let Ok(found) = &result {
+        sink.record_schema_lookup(kind, found.is_some(), start.elapsed());
+    }
+    result
+}
+
+async fn observe_with<T, E, F>(
+    sink: &dyn TelemetrySink,
+    operation: &'static str,
+    fut: F,
+) -> Result<T, E>
+where
+    F: std::future::Future<Output = Result<T, E>>,
+{
+    let start = Instant::now();
+    let result = fut.await;
+
+    let outcome = match &result {
+        Ok(_) => Outcome::Success,
+        Err(_) => Outcome::Failure,
+    };
+    sink.record_request(operation, start.elapsed(), outcome);
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct CapturingSink(
+        Mutex<Vec<(&'static str, Outcome)>>,
+        Mutex<Vec<(SchemaKind, bool)>>,
+    );
+
+    impl TelemetrySink for CapturingSink {
+        fn record_request(&self, operation: &'static str, _latency: Duration, outcome: Outcome) {
+            self.0.lock().unwrap().push((operation, outcome));
+        }
+
+        fn record_schema_lookup(&self, kind: SchemaKind, hit: bool, _latency: Duration) {
+            self.1.lock().unwrap().push((kind, hit));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observe_records_requests() {
+        let sink = CapturingSink::default();
+
+        let ok: Result<u32, &str> = observe_with(&sink, "fetch_spec", async { Ok(42) }).await;
+        assert_eq!(ok, Ok(42));
+
+        let err: Result<u32, &str> =
+            observe_with(&sink, "list_fragments", async { Err("whoops") }).await;
+        assert_eq!(err, Err("whoops"));
+
+        assert_eq!(
+            *sink.0.lock().unwrap(),
+            vec![
+                ("fetch_spec", Outcome::Success),
+                ("list_fragments", Outcome::Failure),
+            ]
+        );
+    }
+    #[tokio::test]
+    async fn test_observe_schema_lookups() {
+        let sink = CapturingSink::default();
+
+        let hit: Result<_, &str> =
+            observe_schema_lookup_with(&sink, SchemaKind::Key, async { Ok(Some(7)) }).await;
+        assert_eq!(hit, Ok(Some(7)));
+
+        let miss: Result<Option<u32>, &str> =
+            observe_schema_lookup_with(&sink, SchemaKind::Value, async { Ok(None) }).await;
+        assert_eq!(miss, Ok(None));
+
+        let err: Result<Option<u32>, &str> =
+            observe_schema_lookup_with(&sink, SchemaKind::Value, async { Err("whoops") }).await;
+        assert_eq!(err, Err("whoops"));
+
+        // Failed lookups are neither hits nor misses.
+        assert_eq!(
+            *sink.1.lock().unwrap(),
+            vec![(SchemaKind::Key, true), (SchemaKind::Value, false)]
+        );
+        assert!(sink.0.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,696 @@
+use super::{Collection, RegistryId, SchemaFormat};
+use gazette::journal;
+use proto_flow::flow;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// SpecCache caches the specs of collections fetched from the control plane,
+/// so that Collections built repeatedly (such as for each Metadata request of a
+/// session) needn't each re-query them. Entries are keyed by the principal which
+/// fetched them and the collection name, so that clients having different
+/// authorizations don't share them.
+#[derive(Debug, Default)]
+pub struct SpecCache(Mutex<BTreeMap<CacheKey, (std::time::Instant, Option<CachedSpec>)>>);
+
+// Cache entries are keyed by the principal of the client which builds
+// a collection (None if anonymous), and the collection's name.
+type CacheKey = (Option<String>, String);
+
+fn cache_key(principal: Option<&str>, collection: &str) -> CacheKey {
+    (principal.map(str::to_string), collection.to_string())
+}
+
+// A fetched collection spec and its `updated_at`.
+type CachedSpec = (flow::CollectionSpec, String);
+
+impl SpecCache {
+    /// Return the spec of `collection` cached for `principal` no more than `ttl` before
+    /// `now`, or await `fetch` and cache its result. A zero `ttl` always awaits `fetch`.
+    pub(super) async fn get_or_fetch<F>(
+        &self,
+        principal: Option<&str>,
+        collection: &str,
+        ttl: std::time::Duration,
+        now: std::time::Instant,
+        fetch: F,
+    ) -> anyhow::Result<Option<CachedSpec>>
+    where
+        F: std::future::Future<Output = anyhow::Result<Option<CachedSpec>>>,
+    {
+        if ttl.is_zero() {
+            return fetch.await;
+        }
+        let key = cache_key(principal, collection);
+
+        if let Some((fetched_at, spec)) = self.0.lock().unwrap().get(&key) {
+            if now.saturating_duration_since(*fetched_at) < ttl {
+                return Ok(spec.clone());
+            }
+        }
+
+        let spec = fetch.await?;
+        self.0.lock().unwrap().insert(key, (now, spec.clone()));
+
+        Ok(spec)
+    }
+}
+
+/// Cached journal clients are refreshed once they're within this many seconds
+/// of the expiry of their data-plane access token.
+pub const JOURNAL_CLIENT_EXPIRY_MARGIN_SECS: i64 = 60;
+
+/// JournalClientCache caches the data-plane journal clients of collections with
+/// the expiry of their access tokens, so that Collections built repeatedly
+/// needn't each re-authenticate against the control plane. As with SpecCache,
+/// entries are keyed by principal and collection name, so that clients having
+/// different authorizations don't share data-plane tokens.
+///
+/// Each entry has its own lock, which is held while its client is refreshed,
+/// so that concurrent builds of the same collection make just one refresh.
+#[derive(Default)]
+pub struct JournalClientCache(
+    Mutex<BTreeMap<CacheKey, Arc<tokio::sync::Mutex<Option<CachedJournalClient>>>>>,
+);
+
+// A journal client and the expiry of its token, in unix seconds.
+type CachedJournalClient = (journal::Client, i64);
+
+impl std::fmt::Debug for JournalClientCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set()
+            .entries(self.0.lock().unwrap().keys())
+            .finish()
+    }
+}
+
+impl JournalClientCache {
+    /// Return the journal client of `collection` built by `principal` if its token
+    /// expires more than JOURNAL_CLIENT_EXPIRY_MARGIN_SECS after `now` (in unix
+    /// seconds), or await `build` and cache its client. A client whose token has
+    /// no known expiry isn't cached.
+    pub(super) async fn get_or_build<F>(
+        &self,
+        principal: Option<&str>,
+        collection: &str,
+        now: i64,
+        build: F,
+    ) -> anyhow::Result<journal::Client>
+    where
+        F: std::future::Future<Output = anyhow::Result<(journal::Client, Option<i64>)>>,
+    {
+        let entry = self
+            .0
+            .lock()
+            .unwrap()
+            .entry(cache_key(principal, collection))
+            .or_default()
+            .clone();
+        let mut entry = entry.lock().await;
+
+        if let Some((client, expires_at)) = &*entry {
+            if now + JOURNAL_CLIENT_EXPIRY_MARGIN_SECS < *expires_at {
+                return Ok(client.clone());
+            }
+        }
+
+        let (client, expires_at) = build.await?;
+        *entry = expires_at.map(|expires_at| (client.clone(), expires_at));
+
+        Ok(client)
+    }
+}
+
+/// CollectionFlights de-duplicates concurrent builds of the same collection,
+/// so that a burst of clients which open a newly-seen topic together make a
+/// single build (and a single spec fetch, authorization, and partition listing)
+/// whose result they share. Completed builds, including failed ones, aren't
+/// retained, and the next build after a completed one starts anew.
+///
+/// As with SpecCache, flights are keyed by principal and collection name,
+/// so that clients having different authorizations don't share builds.
+#[derive(Default)]
+pub struct CollectionFlights(
+    Mutex<BTreeMap<CacheKey, tokio::sync::watch::Receiver<Option<CollectionFlight>>>>,
+);
+
+// The shared outcome of a build of a collection.
+type CollectionFlight = Result<Option<Arc<Collection>>, Arc<anyhow::Error>>;
+
+impl std::fmt::Debug for CollectionFlights {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set()
+            .entries(self.0.lock().unwrap().keys())
+            .finish()
+    }
+}
+
+impl CollectionFlights {
+    /// Await the in-flight build of `collection` by `principal`, or await `build` as
+    /// the in-flight build if there isn't one. A failed build fails all of its callers with a
+    /// SharedBuildError, through which its TopologyError (if any) remains visible
+    /// to `kafka_error`.
+    pub(super) async fn get_or_build<F>(
+        &self,
+        principal: Option<&str>,
+        collection: &str,
+        build: F,
+    ) -> anyhow::Result<Option<Arc<Collection>>>
+    where
+        F: std::future::Future<Output = anyhow::Result<Option<Collection>>>,
+    {
+        let shared = |err: Arc<anyhow::Error>| {
+            anyhow::Error::new(SharedBuildError {
+                collection: collection.to_string(),
+                err,
+            })
+        };
+
+        let key = cache_key(principal, collection);
+
+        let tx = loop {
+            let mut rx = {
+                let mut flights = self.0.lock().unwrap();
+                match flights.get(&key) {
+                    Some(rx) => rx.clone(),
+                    None => {
+                        let (tx, rx) = tokio::sync::watch::channel(None);
+                        flights.insert(key.clone(), rx);
+                        break tx;
+                    }
+                }
+            };
+            // If the in-flight build is abandoned before it completes,
+            // because its caller was cancelled, then try again.
+            while rx.changed().await.is_ok() {
+                if let Some(flight) = rx.borrow_and_update().clone() {
+                    return flight.map_err(shared);
+                }
+            }
+        };
+
+        // The flight is removed once it completes, or if it's cancelled.
+        struct FlightGuard<'a>(&'a CollectionFlights, CacheKey);
+
+        impl Drop for FlightGuard<'_> {
+            fn drop(&mut self) {
+                self.0 .0.lock().unwrap().remove(&self.1);
+            }
+        }
+        let _guard = FlightGuard(self, key);
+
+        let flight = build
+            .await
+            .map(|collection| collection.map(Arc::new))
+            .map_err(Arc::new);
+        tx.send_replace(Some(flight.clone()));
+
+        flight.map_err(shared)
+    }
+}
+
+/// SharedBuildError is the failure of a Collection build which was shared
+/// through CollectionFlights. Its source is the failure of the build.
+#[derive(Debug)]
+pub struct SharedBuildError {
+    pub collection: String,
+    err: Arc<anyhow::Error>,
+}
+
+impl std::fmt::Display for SharedBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to build collection {}", self.collection)
+    }
+}
+
+impl std::error::Error for SharedBuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&**self.err)
+    }
+}
+
+/// EncodeBufferPool recycles the buffers into which reads encode the keys and
+/// values of records, so that a buffer's allocation is re-used by later batches
+/// of any Read. At most `capacity` idle buffers are retained.
+/// Buffers are taken as an EncodeBuffer, which returns its buffer when dropped.
+pub struct EncodeBufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    capacity: usize,
+}
+
+impl std::fmt::Debug for EncodeBufferPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncodeBufferPool")
+            .field("idle", &self.buffers.lock().unwrap().len())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl EncodeBufferPool {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Take an empty buffer from the pool, or allocate one if it has none.
+    pub fn take(self: &Arc<Self>) -> EncodeBuffer {
+        EncodeBuffer {
+            buffer: self.buffers.lock().unwrap().pop().unwrap_or_default(),
+            pool: Some(self.clone()),
+        }
+    }
+
+    /// Return `buffer` to the pool. It's cleared so that its content never
+    /// reaches a later user, and is dropped if the pool is at capacity.
+    fn give(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.capacity {
+            buffers.push(buffer);
+        }
+    }
+}
+
+/// EncodeBuffer is a buffer taken from an EncodeBufferPool, which is returned
+/// to its pool when dropped, including by a user which fails part-way.
+/// A default EncodeBuffer has no pool, and its buffer is simply dropped.
+#[derive(Debug, Default)]
+pub struct EncodeBuffer {
+    buffer: Vec<u8>,
+    pool: Option<Arc<EncodeBufferPool>>,
+}
+
+impl std::ops::Deref for EncodeBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl std::ops::DerefMut for EncodeBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+impl Drop for EncodeBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.give(std::mem::take(&mut self.buffer));
+        }
+    }
+}
+
+/// SchemaIdCache caches the registry IDs of schemas by their content-addressed
+/// MD5, so that a schema already resolved by this process needn't be looked up
+/// again. A schema's registry ID never changes, so entries are never invalidated,
+/// but at most `capacity` are retained and the least-recently used is evicted.
+/// Registry IDs are global, so a SchemaIdCache may be shared by clients having
+/// different authorizations.
+pub struct SchemaIdCache {
+    entries: Mutex<SchemaIdEntries>,
+    capacity: usize,
+}
+
+// Entries of a SchemaIdCache, with an index of their last use.
+#[derive(Default)]
+struct SchemaIdEntries {
+    by_md5: BTreeMap<String, (RegistryId, u64)>,
+    by_use: BTreeMap<u64, String>,
+    next_use: u64,
+}
+
+impl std::fmt::Debug for SchemaIdCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SchemaIdCache")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl SchemaIdCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(SchemaIdEntries::default()),
+            capacity,
+        }
+    }
+
+    /// Number of cached registry IDs.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().by_md5.len()
+    }
+
+    /// Is the cache empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the cached registry ID of schema `md5` in `format`, if any,
+    /// marking it as most-recently used.
+    pub fn get(&self, format: SchemaFormat, md5: &str) -> Option<RegistryId> {
+        let mut entries = self.entries.lock().unwrap();
+        let use_ = entries.next_use;
+
+        let (id, last_use) = entries.by_md5.get_mut(md5)?;
+        if id.format != format {
+            return None;
+        }
+        let (id, last_use) = (*id, std::mem::replace(last_use, use_));
+
+        let md5 = entries.by_use.remove(&last_use).unwrap();
+        entries.by_use.insert(use_, md5);
+        entries.next_use += 1;
+
+        Some(id)
+    }
+
+    /// Cache registry `id` of schema `md5`, evicting the least-recently used
+    /// entry if the cache is beyond capacity.
+    pub fn insert(&self, md5: &str, id: RegistryId) {
+        let mut entries = self.entries.lock().unwrap();
+        let use_ = entries.next_use;
+        entries.next_use += 1;
+
+        if let Some((_, last_use)) = entries.by_md5.insert(md5.to_string(), (id, use_)) {
+            entries.by_use.remove(&last_use);
+        }
+        entries.by_use.insert(use_, md5.to_string());
+
+        while entries.by_md5.len() > self.capacity {
+            let oldest = *entries.by_use.keys().next().unwrap();
+            let evicted = entries.by_use.remove(&oldest).unwrap();
+            entries.by_md5.remove(&evicted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::topology::test::{collection_fixture, spec_fixture};
+    use crate::topology::{kafka_error, TopologyError};
+    use kafka_protocol::error::ResponseError;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_schema_id_cache_evicts_least_recently_used() {
+        let cache = SchemaIdCache::new(2);
+        let avro = |id| RegistryId {
+            format: SchemaFormat::Avro,
+            id,
+        };
+        cache.insert("md5-a", avro(1));
+        cache.insert("md5-b", avro(2));
+
+        // A lookup of the wrong format misses.
+        assert_eq!(cache.get(SchemaFormat::JsonSchema, "md5-a"), None);
+        // Using "a" makes "b" the least-recently used, which is evicted.
+        assert_eq!(cache.get(SchemaFormat::Avro, "md5-a"), Some(avro(1)));
+        cache.insert("md5-c", avro(3));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(SchemaFormat::Avro, "md5-b"), None);
+        assert_eq!(cache.get(SchemaFormat::Avro, "md5-a"), Some(avro(1)));
+        assert_eq!(cache.get(SchemaFormat::Avro, "md5-c"), Some(avro(3)));
+
+        // Re-inserting an entry doesn't grow the cache.
+        cache.insert("md5-c", avro(3));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_spec_cache_ttl() {
+        use std::time::{Duration, Instant};
+
+        let cache = SpecCache::default();
+        let fetches = AtomicUsize::new(0);
+        let fetch = || {
+            let fetches = &fetches;
+            async move {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, anyhow::Error>(Some((spec_fixture(), "2024-01-01T00:00:00Z".to_string())))
+            }
+        };
+        let (ttl, t0) = (Duration::from_secs(60), Instant::now());
+
+        // The first lookup fetches, and a lookup within the TTL is a cache hit.
+        let first = cache
+            .get_or_fetch(None, "acmeCo/things", ttl, t0, fetch())
+            .await;
+        let second = cache
+            .get_or_fetch(
+                None,
+                "acmeCo/things",
+                ttl,
+                t0 + Duration::from_secs(59),
+                fetch(),
+            )
+            .await;
+        assert_eq!(first.unwrap(), second.unwrap());
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // Specs fetched by another principal aren't shared.
+        cache
+            .get_or_fetch(Some("other-token"), "acmeCo/things", ttl, t0, fetch())
+            .await
+            .unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // An expired entry is re-fetched.
+        let expired = t0 + Duration::from_secs(60);
+        cache
+            .get_or_fetch(None, "acmeCo/things", ttl, expired, fetch())
+            .await
+            .unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+        // Which refreshes it.
+        cache
+            .get_or_fetch(
+                None,
+                "acmeCo/things",
+                ttl,
+                expired + Duration::from_secs(1),
+                fetch(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+        // A zero TTL always fetches, as when there's no cache.
+        for _ in 0..2 {
+            cache
+                .get_or_fetch(None, "acmeCo/things", Duration::ZERO, expired, fetch())
+                .await
+                .unwrap();
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_journal_client_cache() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let cache = JournalClientCache::default();
+        let builds = AtomicUsize::new(0);
+
+        let build = |expires_at: Option<i64>| {
+            let builds = &builds;
+            async move {
+                builds.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+                let router = gazette::Router::new("http://localhost:8080", "local").unwrap();
+                let client = journal::Client::new(Default::default(), router, Default::default());
+                Ok((client, expires_at))
+            }
+        };
+
+        // A stampede of concurrent builds makes a single refresh.
+        let results = futures::future::join_all(
+            (0..10).map(|_| cache.get_or_build(None, "acmeCo/things", 1000, build(Some(2000)))),
+        )
+        .await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+
+        // The cached client is returned until it nears expiry.
+        cache
+            .get_or_build(None, "acmeCo/things", 1900, build(Some(3000)))
+            .await
+            .unwrap();
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+
+        cache
+            .get_or_build(None, "acmeCo/things", 1950, build(Some(3000)))
+            .await
+            .unwrap();
+        assert_eq!(builds.load(Ordering::SeqCst), 2);
+
+        // Clients of other collections are cached independently, and a client
+        // without a known expiry isn't cached.
+        for _ in 0..2 {
+            cache
+                .get_or_build(None, "acmeCo/other", 1000, build(None))
+                .await
+                .unwrap();
+        }
+        assert_eq!(builds.load(Ordering::SeqCst), 4);
+
+        // As are clients built by other principals.
+        for _ in 0..2 {
+            cache
+                .get_or_build(
+                    Some("other-token"),
+                    "acmeCo/things",
+                    1950,
+                    build(Some(3000)),
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(builds.load(Ordering::SeqCst), 5);
+        assert_eq!(
+            format!("{cache:?}"),
+            r#"{(None, "acmeCo/other"), (None, "acmeCo/things"), (Some("other-token"), "acmeCo/things")}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collection_flights() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let flights = CollectionFlights::default();
+        let builds = AtomicUsize::new(0);
+
+        let build = |ok: bool| {
+            let builds = &builds;
+            async move {
+                builds.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+                if ok {
+                    Ok(Some(collection_fixture(spec_fixture())))
+                } else {
+                    Err(anyhow::Error::from(TopologyError::CollectionNotFound {
+                        collection: "acmeCo/things".to_string(),
+                    })
+                    .context("fetching collection spec"))
+                }
+            }
+        };
+
+        // Concurrent builds of a collection share a single build and its result.
+        let results = futures::future::join_all(
+            (0..10).map(|_| flights.get_or_build(None, "acmeCo/things", build(true))),
+        )
+        .await;
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+
+        let first = results[0].as_ref().unwrap().as_ref().unwrap();
+        assert!(results
+            .iter()
+            .all(|result| Arc::ptr_eq(result.as_ref().unwrap().as_ref().unwrap(), first)));
+
+        // Builds by different principals aren't shared.
+        let results = futures::future::join(
+            flights.get_or_build(Some("a-token"), "acmeCo/things", build(true)),
+            flights.get_or_build(Some("b-token"), "acmeCo/things", build(true)),
+        )
+        .await;
+        assert!(results.0.is_ok() && results.1.is_ok());
+        assert_eq!(builds.load(Ordering::SeqCst), 3);
+
+        // A failure is propagated to every caller, with its TopologyError.
+        let results = futures::future::join_all(
+            (0..3).map(|_| flights.get_or_build(None, "acmeCo/things", build(false))),
+        )
+        .await;
+        assert_eq!(builds.load(Ordering::SeqCst), 4);
+
+        for result in results {
+            let err = result.err().unwrap();
+            assert_eq!(
+                kafka_error(&err),
+                Some(ResponseError::UnknownTopicOrPartition)
+            );
+            assert!(format!("{err:#}").contains("does not exist"));
+        }
+
+        // Neither a success nor a failure is retained, and the next build starts anew.
+        assert!(flights.0.lock().unwrap().is_empty());
+        flights
+            .get_or_build(None, "acmeCo/things", build(true))
+            .await
+            .unwrap();
+        assert_eq!(builds.load(Ordering::SeqCst), 5);
+
+        // A cancelled build is abandoned, and a waiting build takes its place.
+        let mut cancelled = Box::pin(flights.get_or_build(None, "acmeCo/things", build(true)));
+        let waiting = flights.get_or_build(None, "acmeCo/things", build(true));
+        tokio::pin!(waiting);
+
+        assert!(futures::poll!(&mut cancelled).is_pending());
+        assert!(futures::poll!(&mut waiting).is_pending());
+        drop(cancelled);
+        assert!(waiting.await.unwrap().is_some());
+        assert_eq!(builds.load(Ordering::SeqCst), 7);
+    }
+
+    #[test]
+    fn test_encode_buffer_pool() {
+        let pool = Arc::new(EncodeBufferPool::new(1));
+        let (_key_schema, value_schema) = avro::json_schema_to_avro(
+            &spec_fixture().write_schema_json,
+            &[doc::Pointer::from_str("/id")],
+        )
+        .unwrap();
+
+        let encode = |doc: serde_json::Value| {
+            let mut b = pool.take();
+            avro::encode(&mut b, &value_schema, &doc).unwrap();
+            b.to_vec() // `b` is returned to the pool.
+        };
+        let long = encode(json!({"id": "a-much-longer-identifier"}));
+
+        // A recycled buffer retains its allocation, but not its content,
+        // so a shorter record doesn't carry bytes of the longer one.
+        let recycled = pool.take();
+        assert!(recycled.is_empty());
+        assert!(recycled.capacity() >= long.len());
+        drop(recycled);
+
+        let short = encode(json!({"id": "b"}));
+        let mut expect = Vec::new();
+        avro::encode(&mut expect, &value_schema, &json!({"id": "b"})).unwrap();
+        assert_eq!(short, expect);
+
+        // A buffer is returned if its user fails part-way.
+        let failed = || -> anyhow::Result<()> {
+            let mut b = pool.take();
+            b.push(1);
+            anyhow::bail!("failed to encode")
+        };
+        assert!(failed().is_err());
+        assert!(pool.take().is_empty());
+        assert_eq!(
+            format!("{pool:?}"),
+            "EncodeBufferPool { idle: 1, capacity: 1 }"
+        );
+
+        // Buffers beyond capacity are dropped.
+        let (first, second) = (pool.take(), pool.take());
+        drop((first, second));
+        assert_eq!(
+            format!("{pool:?}"),
+            "EncodeBufferPool { idle: 1, capacity: 1 }"
+        );
+    }
+}
@@ -0,0 +1,779 @@
+/// CanonicalFormPolicy determines how a schema is content-addressed if its
+/// normalized form, such as its full form including defaults, fails to
+/// serialize as JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CanonicalFormPolicy {
+    /// Fail schema registration with an error.
+    #[default]
+    Strict,
+    /// Fall back to content-addressing the Avro canonical form of the schema,
+    /// which omits attributes such as field defaults.
+    FallbackToRaw,
+}
+
+/// SchemaNormalizer maps an Avro schema into the JSON document under which it's
+/// registered and content-addressed. Schemas having equal documents share a
+/// registry ID, so a normalizer determines which schema differences matter.
+pub trait SchemaNormalizer: Send + Sync {
+    /// Map `schema` into its normalized JSON document.
+    fn normalize(&self, schema: &avro::Schema) -> serde_json::Result<serde_json::Value>;
+}
+
+/// FullForm normalizes a schema into its full form, including metadata which
+/// is required while encoding such as field defaults, so that schemas which
+/// differ only in such metadata have distinct registry IDs. It changes the
+/// content address of every schema, and so re-registers them all under new IDs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FullForm;
+
+impl SchemaNormalizer for FullForm {
+    fn normalize(&self, schema: &avro::Schema) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(schema)
+    }
+}
+
+/// CanonicalForm normalizes a schema into its Avro Parsing Canonical Form,
+/// which omits attributes such as field defaults and docs, so that schemas
+/// which differ only in those attributes share a registry ID. It's the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CanonicalForm;
+
+impl SchemaNormalizer for CanonicalForm {
+    fn normalize(&self, schema: &avro::Schema) -> serde_json::Result<serde_json::Value> {
+        serde_json::from_str(&schema.canonical_form())
+    }
+}
+
+/// SchemaCompatibilityPolicy determines whether a new schema of a collection
+/// is checked against the collection's previously-registered schema before
+/// it's registered. Only Avro schemas are checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchemaCompatibilityPolicy {
+    /// Register new schemas without checking them, such as for collections
+    /// which intentionally break compatibility.
+    #[default]
+    Unchecked,
+    /// Require that records written with a new schema can be read by readers of
+    /// the most-recently registered schema of the same kind (key or value),
+    /// failing with TopologyError::SchemaIncompatible if they can't.
+    Backward,
+}
+
+/// RegistryFailurePolicy determines how a Collection's schemas are identified
+/// if their registry IDs can't be resolved, such as if the control plane
+/// hosting the schema registry is unavailable.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegistryFailurePolicy {
+    /// Fail the resolution, which makes the collection's topic unserviceable.
+    #[default]
+    Fail,
+    /// Log the failure and identify the schema by UNREGISTERED_SCHEMA_ID, so
+    /// that records remain readable. This trades the correctness of the schema
+    /// registry for availability: consumers can't resolve the schemas of records.
+    Degrade,
+}
+
+/// Registry ID of schemas whose registration failed under RegistryFailurePolicy::Degrade.
+/// Registry IDs are assigned from one, so no registered schema has this ID.
+pub const UNREGISTERED_SCHEMA_ID: u32 = 0;
+
+/// Format determines how documents are encoded as Kafka record values.
+/// Record keys are always encoded in the collection's SchemaFormat.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Encoding using the collection's registered value schema, in its
+    /// SchemaFormat, prefixed by the Confluent wire-format header.
+    #[default]
+    Avro,
+    /// MessagePack encoding of the document, having object properties ordered
+    /// by the fields of the collection's value schema.
+    MessagePack,
+}
+
+/// ReadMode determines how documents of a partition are mapped into Kafka records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadMode {
+    /// Each document is emitted as it was written.
+    #[default]
+    Documents,
+    /// If the collection schema reduces documents as partial "delta" updates,
+    /// each document is reduced into the prior state of its key and the
+    /// reduced state is emitted. Other collections are read as Documents.
+    ReduceDeltas,
+}
+
+/// UuidPtrPolicy determines how a collection having an empty or invalid
+/// `uuid_ptr` is handled. Such a collection's documents have no UUIDs
+/// by which transaction ACKs are filtered and timestamps are extracted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UuidPtrPolicy {
+    /// Fail to build the Collection with an error.
+    #[default]
+    Strict,
+    /// Build the Collection with a warning, and read all its documents
+    /// as non-ACK documents having a zero timestamp.
+    Lenient,
+}
+
+/// MissingFragmentSpecPolicy determines how a listed fragment which lacks
+/// a spec is handled when mapping a timestamp into an offset.
+/// This indicates a broker inconsistency, rather than an absence of data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingFragmentSpecPolicy {
+    /// Log a warning and map the timestamp as if no fragment were found.
+    #[default]
+    Warn,
+    /// Fail with an error.
+    Strict,
+}
+
+/// PartitionFanOut exposes each journal of a collection as `shards` Kafka
+/// partitions, for parallel consumption of a high-throughput journal.
+///
+/// The journal's offsets are striped by `stripe_bytes`, and stripes are dealt
+/// to shards round-robin, so shards cover disjoint offset ranges and each grows
+/// as the journal is written. A document belongs to the stripe of its last byte,
+/// which is also its Kafka offset. Kafka partition `journal_index * shards + shard`
+/// is shard `shard` of the journal `journal_index`.
+///
+/// Documents remain ordered within a shard, but there's no ordering across the
+/// shards of a journal: documents of the same key may be read out of order by
+/// different consumers, and a consumer of one shard reads (and then skips) the
+/// documents of every other shard, multiplying the journal's read bandwidth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PartitionFanOut {
+    shards: u32,
+    stripe_bytes: i64,
+}
+
+impl PartitionFanOut {
+    /// Build a PartitionFanOut of `shards` Kafka partitions per journal,
+    /// striped by `stripe_bytes`. Both must be positive.
+    pub fn new(shards: u32, stripe_bytes: i64) -> anyhow::Result<Self> {
+        if shards == 0 {
+            anyhow::bail!("PartitionFanOut shards must be positive");
+        }
+        if stripe_bytes <= 0 {
+            anyhow::bail!("PartitionFanOut stripe_bytes must be positive, not {stripe_bytes}");
+        }
+        Ok(Self {
+            shards,
+            stripe_bytes,
+        })
+    }
+
+    /// Number of Kafka partitions of each journal.
+    pub fn shards(&self) -> u32 {
+        self.shards
+    }
+
+    /// Number of bytes of each stripe of a journal's offsets.
+    pub fn stripe_bytes(&self) -> i64 {
+        self.stripe_bytes
+    }
+
+    /// Shard of the document having Kafka offset `kafka_offset`.
+    pub fn shard_of(&self, kafka_offset: i64) -> u32 {
+        (kafka_offset.max(0) / self.stripe_bytes % self.shards as i64) as u32
+    }
+
+    /// Map a Kafka partition into its (journal index, shard).
+    pub fn resolve(&self, kafka_partition: usize) -> (usize, u32) {
+        let shards = self.shards as usize;
+        (kafka_partition / shards, (kafka_partition % shards) as u32)
+    }
+
+    /// Offset ranges of `shard` which intersect `[begin, end)`, in offset order.
+    pub fn shard_ranges(&self, shard: u32, begin: i64, end: i64) -> Vec<(i64, i64)> {
+        let period = self.stripe_bytes * self.shards as i64;
+        let mut stripe = begin.max(0) / period * period + shard as i64 * self.stripe_bytes;
+        let mut ranges = Vec::new();
+
+        while stripe < end {
+            let range = (stripe.max(begin), (stripe + self.stripe_bytes).min(end));
+            if range.0 < range.1 {
+                ranges.push(range);
+            }
+            stripe += period;
+        }
+        ranges
+    }
+}
+
+/// SchemaSelection determines which JSON schema of a collection is mapped
+/// into its Avro schemas, and against which its key is validated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchemaSelection {
+    /// Use the read schema, or the write schema if the collection has no read schema.
+    #[default]
+    PreferRead,
+    /// Use the write schema, which includes fields stripped by a read projection.
+    ForceWrite,
+    /// Use the read schema, and fail if the collection has no read schema.
+    ForceRead,
+}
+
+/// JsonSchemaSource is the JSON schema of a collection which was selected per
+/// its SchemaSelection, and from which its Avro schemas were derived.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsonSchemaSource {
+    /// The read schema, whose fields are those which consumers see.
+    Read,
+    /// The write schema, because the collection has no read schema or
+    /// SchemaSelection::ForceWrite was used.
+    Write,
+}
+
+/// DeletionPolicy determines which documents of a collection are deletions
+/// of their keys. Deletions are read as tombstone records, which have a key
+/// and a null value, as do deletions of Kafka's compacted topics.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DeletionPolicy {
+    /// Documents whose `/_meta/op` is "d", as captures mark deleted source rows.
+    #[default]
+    MetaOp,
+    /// Documents having `true` at the given JSON pointer, such as "/deleted".
+    Pointer(String),
+    /// Documents whose reduction into the prior state of their key is a deletion,
+    /// per the `delete` annotations of the collection's write schema.
+    /// This applies only to ReadMode::ReduceDeltas, and otherwise nothing is deleted.
+    Reduction,
+    /// No document is a deletion.
+    Never,
+}
+
+impl DeletionPolicy {
+    /// Map the policy into the DeletionRule of a Collection.
+    pub fn rule(&self) -> DeletionRule {
+        match self {
+            Self::MetaOp => {
+                DeletionRule::Equals(doc::Pointer::from_str("/_meta/op"), "d".to_string())
+            }
+            Self::Pointer(ptr) => DeletionRule::True(doc::Pointer::from_str(ptr)),
+            Self::Reduction => DeletionRule::Reduction,
+            Self::Never => DeletionRule::Never,
+        }
+    }
+}
+
+/// DeletionRule is the explicit rule by which the documents of a Collection
+/// are deletions of their keys, as determined by its DeletionPolicy.
+#[derive(Clone, Debug)]
+pub enum DeletionRule {
+    /// Documents having the given string at the pointer.
+    Equals(doc::Pointer, String),
+    /// Documents having `true` at the pointer.
+    True(doc::Pointer),
+    /// Documents whose reduction is a deletion, which isn't known from the document alone.
+    Reduction,
+    /// No document is a deletion.
+    Never,
+}
+
+impl DeletionRule {
+    /// Whether `doc` is itself a deletion under the rule.
+    pub fn is_deletion<N: doc::AsNode>(&self, doc: &N) -> bool {
+        match self {
+            Self::Equals(ptr, value) => matches!(
+                ptr.query(doc).map(doc::AsNode::as_node),
+                Some(doc::Node::String(s)) if s == value.as_str()
+            ),
+            Self::True(ptr) => matches!(
+                ptr.query(doc).map(doc::AsNode::as_node),
+                Some(doc::Node::Bool(true))
+            ),
+            Self::Reduction | Self::Never => false,
+        }
+    }
+}
+
+/// DocumentFilter is a conjunction of conditions, all of which a document must match
+/// to be read. Other documents are skipped before they're encoded, as if they were
+/// of another partition. An empty filter (the default) matches every document.
+#[derive(Clone, Debug, Default)]
+pub struct DocumentFilter {
+    pub conditions: Vec<FilterCondition>,
+}
+
+/// FilterCondition compares the document location at `ptr` with `value` by `op`.
+#[derive(Clone, Debug)]
+pub struct FilterCondition {
+    pub ptr: doc::Pointer,
+    pub op: FilterOp,
+    /// Value compared with the document location, which is ignored by FilterOp::Exists.
+    pub value: serde_json::Value,
+}
+
+/// FilterOp is the comparison of a FilterCondition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterOp {
+    /// The location exists and equals the value.
+    Equals,
+    /// The location is missing or doesn't equal the value.
+    NotEquals,
+    /// The location is less than the value. Locations which are missing,
+    /// or aren't of the value's type, don't match any ordered comparison.
+    LessThan,
+    /// The location is less than or equal to the value.
+    LessThanOrEqual,
+    /// The location is greater than the value.
+    GreaterThan,
+    /// The location is greater than or equal to the value.
+    GreaterThanOrEqual,
+    /// The location exists.
+    Exists,
+}
+
+impl DocumentFilter {
+    /// Whether `doc` matches every condition of the filter.
+    pub fn matches<N: doc::AsNode>(&self, doc: &N) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(doc))
+    }
+}
+
+impl FilterCondition {
+    /// Whether `doc` matches the condition.
+    pub fn matches<N: doc::AsNode>(&self, doc: &N) -> bool {
+        use std::cmp::Ordering;
+
+        let Some(node) = self.ptr.query(doc) else {
+            return self.op == FilterOp::NotEquals;
+        };
+        let ordering = doc::compare(node, &self.value);
+
+        // Ordered comparisons are only meaningful between values of the same type.
+        let comparable = matches!(
+            (
+                doc::AsNode::as_node(node),
+                doc::AsNode::as_node(&self.value)
+            ),
+            (
+                doc::Node::PosInt(_) | doc::Node::NegInt(_) | doc::Node::Float(_),
+                doc::Node::PosInt(_) | doc::Node::NegInt(_) | doc::Node::Float(_),
+            ) | (doc::Node::String(_), doc::Node::String(_))
+        );
+
+        match self.op {
+            FilterOp::Equals => ordering == Ordering::Equal,
+            FilterOp::NotEquals => ordering != Ordering::Equal,
+            FilterOp::LessThan => comparable && ordering == Ordering::Less,
+            FilterOp::LessThanOrEqual => comparable && ordering != Ordering::Greater,
+            FilterOp::GreaterThan => comparable && ordering == Ordering::Greater,
+            FilterOp::GreaterThanOrEqual => comparable && ordering != Ordering::Less,
+            FilterOp::Exists => true,
+        }
+    }
+}
+
+/// ValueFieldsPolicy determines the fields of record values and their value schema.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValueFieldsPolicy {
+    /// Values are the collection's documents.
+    #[default]
+    Document,
+    /// Values are flat records having a field of each explicit projection of
+    /// the collection, named by the projection and holding the document value
+    /// at its pointer. Requires SchemaSource::Compute and SchemaFormat::Avro.
+    Projections,
+}
+
+/// SchemaSource determines how a Collection's key and value Avro schemas are resolved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchemaSource {
+    /// Map the collection's JSON schema into Avro schemas.
+    #[default]
+    Compute,
+    /// Use the most-recent key and value schemas of the collection which were
+    /// registered since its spec was last updated, and compute them otherwise.
+    /// Registered schemas are content-addressed as normalized by the
+    /// process-wide SchemaNormalizer.
+    Registry,
+}
+
+/// TokenScopePolicy determines whether the claims of a data-plane token are
+/// checked to grant reads of a collection before the token is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TokenScopePolicy {
+    /// Fail with TopologyError::InsufficientScope if the token doesn't grant reads.
+    #[default]
+    Strict,
+    /// Use the token without checking its claims.
+    Unchecked,
+}
+
+/// MetaFieldsPolicy determines whether the `_meta` property of documents,
+/// holding Flow's document UUID and other metadata, is part of record values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MetaFieldsPolicy {
+    /// Include `_meta` in record values, as mapped from the collection schema.
+    #[default]
+    Include,
+    /// Strip `_meta` from the value schema and from encoded record values.
+    Strip,
+}
+
+/// KeySource determines which document location keys Kafka records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeySource {
+    /// Key records by the components of the collection key.
+    #[default]
+    CollectionKey,
+    /// Key records by the document UUID at the collection's `uuid_ptr`,
+    /// such that every record has a unique key.
+    DocumentUuid,
+}
+
+/// EnumMappingPolicy determines how a JSON schema `enum` of strings
+/// is represented within the collection's Avro value schema.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EnumMappingPolicy {
+    /// Map enumerations into Avro strings.
+    #[default]
+    String,
+    /// Map enumerations into Avro enums, where each value is a valid Avro
+    /// enum symbol. Other enumerations are mapped into Avro strings.
+    AvroEnum,
+}
+
+/// PartitionGonePolicy determines how `handle_partition_gone` treats a
+/// TopologyError::PartitionGone, which means a partition's journal was
+/// deleted after the Collection was built.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PartitionGonePolicy {
+    /// Surface the error without changing the Collection.
+    #[default]
+    Fail,
+    /// Also refresh the Collection's partitions, so that later reads
+    /// and offset requests map over only journals which exist.
+    Refresh,
+}
+
+/// SchemaFormat determines the type of the schemas which are registered for
+/// a collection, as a Confluent schema registry `schemaType`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchemaFormat {
+    /// Avro schemas mapped from the collection's JSON schema.
+    /// Records are Avro-encoded.
+    #[default]
+    Avro,
+    /// JSON schemas inferred from the collection's JSON schema.
+    /// Records are encoded as JSON.
+    JsonSchema,
+}
+
+impl SchemaFormat {
+    /// The registry `schemaType` of the format.
+    pub fn schema_type(self) -> &'static str {
+        match self {
+            Self::Avro => "AVRO",
+            Self::JsonSchema => "JSON",
+        }
+    }
+}
+
+/// Name of the record value field which holds a document's publish time,
+/// as an RFC 3339 date-time, if TimestampPolicy places it in the value.
+pub const PUBLISHED_AT_FIELD: &str = "_published_at";
+
+/// TimestampPolicy determines where the publish time of a document,
+/// from its UUID, is placed within its Kafka record.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampPolicy {
+    /// The publish time is the record timestamp.
+    #[default]
+    RecordTimestamp,
+    /// The publish time is the PUBLISHED_AT_FIELD of the record value,
+    /// and the record has no timestamp.
+    ValueField,
+    /// The publish time is both the record timestamp and the PUBLISHED_AT_FIELD.
+    Both,
+}
+
+impl TimestampPolicy {
+    /// Is the publish time the record timestamp?
+    pub fn in_record(self) -> bool {
+        self != Self::ValueField
+    }
+
+    /// Is the publish time a field of the record value?
+    pub fn in_value(self) -> bool {
+        self != Self::RecordTimestamp
+    }
+}
+
+/// EmptyCollectionsPolicy determines how a listing of the collections
+/// which the current user may read handles an empty result.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyCollectionsPolicy {
+    /// An empty listing is returned as such.
+    #[default]
+    Allow,
+    /// An empty listing is TopologyError::NoReadableCollections,
+    /// for callers which expect the user to read at least one collection.
+    Error,
+}
+
+/// BuiltSpecsPolicy determines whether a listing of the collections which
+/// the current user may read includes those without a built specification.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BuiltSpecsPolicy {
+    /// All readable collections are listed, as is useful for admin tooling.
+    #[default]
+    Any,
+    /// Only collections having a built specification, which may be opened, are listed.
+    Required,
+}
+
+/// HighWatermarkSource determines how the high watermark of a partition is resolved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HighWatermarkSource {
+    /// The high watermark is the end of the partition's newest persisted fragment,
+    /// which may lag appends that brokers haven't yet persisted.
+    #[default]
+    Fragment,
+    /// The high watermark is the partition's live write head, as reported by
+    /// its brokers. It falls back to the newest fragment if the write head
+    /// can't be fetched.
+    WriteHead,
+}
+
+/// OpenAllPolicy determines how `Collection::open_all` handles a
+/// collection which fails to open.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OpenAllPolicy {
+    /// Every collection is opened, and each has its own result.
+    #[default]
+    BestEffort,
+    /// The first failure is returned, and opens still in flight are cancelled.
+    FailFast,
+}
+
+/// SchemaMigrationPolicy determines whether documents published under an
+/// older registered value schema are migrated into the current one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchemaMigrationPolicy {
+    /// Documents are encoded directly with the current value schema.
+    #[default]
+    Disabled,
+    /// Documents are encoded with the registered value schema which was current
+    /// at their publish time, and are then resolved into the current value schema,
+    /// so that fields added since take their defaults and renamed fields their values.
+    Resolve,
+}
+
+/// WriterSchema is a value schema which was registered for a collection,
+/// and under which documents published since its registration were written.
+#[derive(Clone, Debug)]
+pub struct WriterSchema {
+    /// Registration time of the schema, in unix seconds.
+    pub registered_at: i64,
+    pub schema: avro::Schema,
+}
+
+/// OffsetBoundary determines the offset to which a timestamp resolves,
+/// within the newest fragment which covers it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OffsetBoundary {
+    /// Resolve to the begin offset of the fragment, which is cheap but may
+    /// include documents published before the timestamp.
+    #[default]
+    ContainingFragmentBegin,
+    /// Resolve to the offset of the first document of the fragment which was
+    /// published at or after the timestamp, by reading into the fragment.
+    /// If no such document exists, resolve to the end of the fragment.
+    AtOrAfter,
+}
+
+/// IsolationLevel determines whether documents of transactions which have not
+/// yet been acknowledged are visible, akin to Kafka's `isolation.level` setting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// The high watermark is the end of the partition's latest fragment,
+    /// which may include documents of unacknowledged transactions.
+    #[default]
+    ReadUncommitted,
+    /// The high watermark is the partition's acknowledged write head: the end
+    /// of the last document of its latest fragment which isn't a continuation
+    /// of a still-open transaction. Collections without UUIDs read uncommitted.
+    ReadCommitted,
+}
+
+/// ReadFromPolicy determines where a read begins if its consumer has no
+/// committed offset, akin to Kafka's `auto.offset.reset` consumer setting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadFromPolicy {
+    /// Begin from the first available offset of the partition.
+    #[default]
+    Earliest,
+    /// Begin from the partition's write head, reading only new documents.
+    Latest,
+}
+
+impl ReadFromPolicy {
+    /// Map the policy into its ListOffsets timestamp sentinel.
+    pub fn timestamp_sentinel(self) -> i64 {
+        match self {
+            Self::Earliest => -2,
+            Self::Latest => -1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::topology::fragment_offset;
+    use gazette::broker;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_from_policy_offsets() {
+        let fragment = broker::Fragment {
+            journal: "example/journal".to_string(),
+            begin: 1024,
+            end: 4096,
+            mod_time: 1_700_000_000,
+            ..Default::default()
+        };
+
+        // Latest begins at the largest fetch-able offset, just before the write head.
+        let sentinel = ReadFromPolicy::Latest.timestamp_sentinel();
+        assert_eq!(fragment_offset(&fragment, sentinel), (4095, 1_700_000_000));
+
+        // Earliest begins at the start of the first available fragment.
+        let sentinel = ReadFromPolicy::Earliest.timestamp_sentinel();
+        assert_eq!(fragment_offset(&fragment, sentinel), (1024, 1_700_000_000));
+
+        assert_eq!(ReadFromPolicy::default(), ReadFromPolicy::Earliest);
+    }
+
+    #[test]
+    fn test_deletion_rules() {
+        let meta_op = DeletionPolicy::default().rule();
+        assert!(meta_op.is_deletion(&json!({"_meta": {"op": "d"}, "id": 1})));
+        assert!(!meta_op.is_deletion(&json!({"_meta": {"op": "u"}, "id": 1})));
+        assert!(!meta_op.is_deletion(&json!({"id": 1})));
+
+        let pointer = DeletionPolicy::Pointer("/deleted".to_string()).rule();
+        assert!(pointer.is_deletion(&json!({"deleted": true})));
+        assert!(!pointer.is_deletion(&json!({"deleted": false})));
+        assert!(!pointer.is_deletion(&json!({"deleted": "true"})));
+        assert!(!pointer.is_deletion(&json!({"_meta": {"op": "d"}})));
+
+        // Neither of these rules deletes a document on its own.
+        for policy in [DeletionPolicy::Reduction, DeletionPolicy::Never] {
+            assert!(!policy.rule().is_deletion(&json!({"_meta": {"op": "d"}})));
+        }
+    }
+
+    #[test]
+    fn test_document_filter() {
+        let condition = |ptr: &str, op, value| FilterCondition {
+            ptr: doc::Pointer::from_str(ptr),
+            op,
+            value,
+        };
+        let doc = json!({"event_type": "purchase", "amount": 42, "tags": ["a"]});
+
+        // An empty filter matches everything.
+        assert!(DocumentFilter::default().matches(&doc));
+
+        for (cond, expect) in [
+            (
+                condition("/event_type", FilterOp::Equals, json!("purchase")),
+                true,
+            ),
+            (
+                condition("/event_type", FilterOp::Equals, json!("refund")),
+                false,
+            ),
+            (
+                condition("/event_type", FilterOp::NotEquals, json!("refund")),
+                true,
+            ),
+            (
+                condition("/missing", FilterOp::NotEquals, json!("refund")),
+                true,
+            ),
+            (condition("/missing", FilterOp::Equals, json!(null)), false),
+            (condition("/amount", FilterOp::Equals, json!(42.0)), true),
+            (condition("/amount", FilterOp::LessThan, json!(50)), true),
+            (condition("/amount", FilterOp::LessThan, json!(42)), false),
+            (
+                condition("/amount", FilterOp::LessThanOrEqual, json!(42)),
+                true,
+            ),
+            (condition("/amount", FilterOp::GreaterThan, json!(-1)), true),
+            (
+                condition("/amount", FilterOp::GreaterThanOrEqual, json!(43)),
+                false,
+            ),
+            // Values of different types aren't ordered.
+            (
+                condition("/amount", FilterOp::GreaterThan, json!("1")),
+                false,
+            ),
+            (condition("/missing", FilterOp::LessThan, json!(1)), false),
+            (condition("/tags", FilterOp::Exists, json!(null)), true),
+            (condition("/missing", FilterOp::Exists, json!(null)), false),
+        ] {
+            let filter = DocumentFilter {
+                conditions: vec![cond.clone()],
+            };
+            assert_eq!(filter.matches(&doc), expect, "{cond:?}");
+        }
+
+        // All conditions must match.
+        let filter = DocumentFilter {
+            conditions: vec![
+                condition("/event_type", FilterOp::Equals, json!("purchase")),
+                condition("/amount", FilterOp::GreaterThan, json!(100)),
+            ],
+        };
+        assert!(!filter.matches(&doc));
+    }
+
+    #[test]
+    fn test_partition_fan_out() {
+        let fan_out = PartitionFanOut::new(3, 1024).unwrap();
+        let (begin, end) = (100, 10_000);
+
+        let mut ranges: Vec<_> = (0..fan_out.shards)
+            .flat_map(|shard| {
+                fan_out
+                    .shard_ranges(shard, begin, end)
+                    .into_iter()
+                    .map(move |range| (range, shard))
+            })
+            .collect();
+        ranges.sort();
+
+        // Ranges of shards are non-overlapping, and together cover [begin, end).
+        assert_eq!(ranges.first().unwrap().0 .0, begin);
+        assert_eq!(ranges.last().unwrap().0 .1, end);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].0 .1, pair[1].0 .0);
+            assert_ne!(pair[0].1, pair[1].1);
+        }
+        // Each offset of a range maps to its shard.
+        for ((low, high), shard) in &ranges {
+            assert_eq!(fan_out.shard_of(*low), *shard);
+            assert_eq!(fan_out.shard_of(*high - 1), *shard);
+        }
+        assert_eq!(ranges[0], ((100, 1024), 0));
+        assert_eq!(ranges[1], ((1024, 2048), 1));
+        assert_eq!(ranges[3], ((3072, 4096), 0));
+
+        // Kafka partitions map into (journal index, shard).
+        assert_eq!(fan_out.resolve(0), (0, 0));
+        assert_eq!(fan_out.resolve(5), (1, 2));
+        assert_eq!(fan_out.resolve(6), (2, 0));
+
+        // Shards and stripes must be positive.
+        assert!(PartitionFanOut::new(0, 1024).is_err());
+        assert!(PartitionFanOut::new(3, 0).is_err());
+        assert!(PartitionFanOut::new(3, -1024).is_err());
+    }
+}
@@ -0,0 +1,1914 @@
+use super::{
+    control_plane_query, control_plane_retry_policy, is_registered_key_schema, registration_limits,
+    retry_postgrest, schema_normalizer, CanonicalFormPolicy, Collection, Options,
+    RegistryFailurePolicy, SchemaCompatibilityPolicy, SchemaFormat, SchemaIds, SchemaNormalizer,
+    TopologyError, SCHEMA_REGISTRY_PERMITS, UNREGISTERED_SCHEMA_ID,
+};
+use crate::telemetry;
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Fetch the schema registered under `registry_id`, as its registry `schemaType`
+/// and stored schema JSON, or None if there's no such schema which the current
+/// user may read. Registered schemas are content-addressed and immutable, so an ID
+/// identifies exactly one schema. A fetched schema is marked as in use by
+/// `mark_schema_used`.
+pub async fn fetch_registered_schema(
+    client: &postgrest::Postgrest,
+    registry_id: u32,
+) -> anyhow::Result<Option<(String, serde_json::Value)>> {
+    #[derive(serde::Deserialize)]
+    struct Row {
+        avro_schema: serde_json::Value,
+        schema_type: String,
+    }
+    let request = client
+        .from("registered_avro_schemas")
+        .eq("registry_id", registry_id.to_string())
+        .select("avro_schema,schema_type");
+
+    let mut rows: Vec<Row> = telemetry::observe("fetch_registered_schema", async move {
+        retry_postgrest(|| request.clone().execute(), control_plane_retry_policy())
+            .await?
+            .error_for_status()
+    })
+    .await
+    .context("querying for an already-registered schema")?
+    .json()
+    .await?;
+
+    if !rows.is_empty() {
+        mark_schema_used(client.clone(), registry_id);
+    }
+    Ok(rows.pop().map(
+        |Row {
+             avro_schema,
+             schema_type,
+         }| (schema_type, avro_schema),
+    ))
+}
+
+/// Bump the `updated_at` of the schema registered under `registry_id` to mark it
+/// as in use. Marking is best-effort and doesn't delay its fetch: the update runs
+/// in the background, and a failure is logged and otherwise ignored.
+fn mark_schema_used(client: postgrest::Postgrest, registry_id: u32) {
+    tokio::spawn(async move {
+        let now = time::OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Rfc3339)
+            .expect("the current time formats as RFC 3339");
+
+        let result = client
+            .from("registered_avro_schemas")
+            .eq("registry_id", registry_id.to_string())
+            .update(serde_json::json!({ "updated_at": now }).to_string())
+            .execute()
+            .await
+            .and_then(|r| r.error_for_status());
+
+        if let Err(err) = result {
+            tracing::debug!(
+                ?err,
+                registry_id,
+                "failed to mark registered schema as in use"
+            );
+        }
+    });
+}
+
+/// Number of schemas fetched by each page of `list_registered_schemas`.
+pub const REGISTERED_SCHEMAS_PAGE_SIZE: usize = 1000;
+
+/// List the schemas registered for `catalog_name`, as (registry ID, stored schema
+/// JSON) in registry ID (registration) order, such as to enumerate the versions of
+/// a registry subject. If `format` is given, only schemas of that format are listed.
+/// Schemas are fetched in pages of REGISTERED_SCHEMAS_PAGE_SIZE, and the listing
+/// doesn't mark them as in use.
+pub async fn list_registered_schemas(
+    client: &postgrest::Postgrest,
+    catalog_name: &str,
+    format: Option<SchemaFormat>,
+) -> anyhow::Result<Vec<(u32, serde_json::Value)>> {
+    list_registered_schemas_paged(client, catalog_name, format, REGISTERED_SCHEMAS_PAGE_SIZE).await
+}
+
+async fn list_registered_schemas_paged(
+    client: &postgrest::Postgrest,
+    catalog_name: &str,
+    format: Option<SchemaFormat>,
+    page_size: usize,
+) -> anyhow::Result<Vec<(u32, serde_json::Value)>> {
+    #[derive(serde::Deserialize)]
+    struct Row {
+        registry_id: u32,
+        avro_schema: serde_json::Value,
+    }
+    let page_size = page_size.max(1);
+    let mut schemas = Vec::new();
+
+    loop {
+        let mut request = client
+            .from("registered_avro_schemas")
+            .eq("catalog_name", catalog_name);
+        if let Some(format) = format {
+            request = request.eq("schema_type", format.schema_type());
+        }
+        let offset = schemas.len();
+        let request = request
+            .select("registry_id,avro_schema")
+            .order("registry_id.asc")
+            .range(offset, offset + page_size - 1);
+
+        let rows: Vec<Row> = control_plane_query("list_registered_schemas", catalog_name, request)
+            .await
+            .with_context(|| format!("listing registered schemas of {catalog_name}"))?;
+
+        let last = rows.len() < page_size; // A short page is the last.
+        schemas.extend(rows.into_iter().map(
+            |Row {
+                 registry_id,
+                 avro_schema,
+             }| (registry_id, avro_schema),
+        ));
+
+        if last {
+            return Ok(schemas);
+        }
+    }
+}
+
+/// SchemaConverter maps a collection's JSON schema and key into its key and
+/// value Avro schemas. Embedders may supply their own converter to apply
+/// different conversion rules, such as for naming, logical types, or flattening.
+pub trait SchemaConverter: std::fmt::Debug + Send + Sync {
+    fn convert(
+        &self,
+        json_schema: &str,
+        key_ptr: &[doc::Pointer],
+    ) -> anyhow::Result<(avro::Schema, avro::Schema)>;
+}
+
+/// SubjectNameStrategy determines the schema registry subjects under which
+/// a topic's key and value schemas are served, akin to the `subject.name.strategy`
+/// of Confluent serializers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SubjectNameStrategy {
+    /// Subjects are `<topic>-key` and `<topic>-value`.
+    #[default]
+    TopicName,
+    /// Subjects are the fully-qualified names of the key and value records,
+    /// which don't identify their topic.
+    RecordName,
+    /// Subjects are `<topic>-<record name>`, for each of the key and value records.
+    TopicRecordName,
+}
+
+/// SubjectSchema is the schema of a topic which a subject identifies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubjectSchema<'s> {
+    Key,
+    Value,
+    /// The key or value schema whose record has this fully-qualified name.
+    Record(&'s str),
+}
+
+impl SubjectNameStrategy {
+    /// Subject of the key (if `is_key`) or value schema of `topic`, which has the
+    /// fully-qualified `record_name` if it's a record. Record-based strategies fall
+    /// back to TopicName subjects for schemas which aren't records.
+    pub fn subject(&self, topic: &str, is_key: bool, record_name: Option<&str>) -> String {
+        match (self, record_name) {
+            (Self::RecordName, Some(record_name)) => record_name.to_string(),
+            (Self::TopicRecordName, Some(record_name)) => format!("{topic}-{record_name}"),
+            _ if is_key => format!("{topic}-key"),
+            _ => format!("{topic}-value"),
+        }
+    }
+
+    /// Parse `subject` into the topic it names, if any, and the schema of it
+    /// which it identifies. This is the inverse of `subject`.
+    pub fn parse_subject<'s>(
+        &self,
+        subject: &'s str,
+    ) -> anyhow::Result<(Option<&'s str>, SubjectSchema<'s>)> {
+        if let Some(topic) = subject.strip_suffix("-value") {
+            return Ok((Some(topic), SubjectSchema::Value));
+        } else if let Some(topic) = subject.strip_suffix("-key") {
+            return Ok((Some(topic), SubjectSchema::Key));
+        }
+        match self {
+            Self::TopicName => anyhow::bail!("expected subject to end with -key or -value"),
+            Self::RecordName => Ok((None, SubjectSchema::Record(subject))),
+            // Avro names never include a '-', so the record name follows the last one.
+            Self::TopicRecordName => match subject.rsplit_once('-') {
+                Some((topic, record_name)) if !topic.is_empty() && !record_name.is_empty() => {
+                    Ok((Some(topic), SubjectSchema::Record(record_name)))
+                }
+                _ => anyhow::bail!("expected subject to be of the form <topic>-<record name>"),
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for SubjectNameStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "topic-name" => Ok(Self::TopicName),
+            "record-name" => Ok(Self::RecordName),
+            "topic-record-name" => Ok(Self::TopicRecordName),
+            _ => anyhow::bail!(
+                "unknown subject name strategy {s:?} (expected topic-name, record-name, or topic-record-name)"
+            ),
+        }
+    }
+}
+
+/// RegistrySchema is a key or value schema of a collection, in its SchemaFormat.
+#[derive(Clone, Debug)]
+pub enum RegistrySchema {
+    Avro(avro::Schema),
+    Json(serde_json::Value),
+}
+
+impl RegistrySchema {
+    pub fn format(&self) -> SchemaFormat {
+        match self {
+            Self::Avro(_) => SchemaFormat::Avro,
+            Self::Json(_) => SchemaFormat::JsonSchema,
+        }
+    }
+
+    /// Fully-qualified name of the schema, if it's an Avro record,
+    /// as used by the record-based SubjectNameStrategy variants.
+    pub fn record_name(&self) -> Option<String> {
+        match self {
+            Self::Avro(avro::Schema::Record(record)) => Some(record.name.fullname(None)),
+            _ => None,
+        }
+    }
+
+    /// The schema as it's served by the registry.
+    pub fn registry_form(&self) -> String {
+        match self {
+            Self::Avro(schema) => schema.canonical_form(),
+            Self::Json(schema) => schema.to_string(),
+        }
+    }
+
+    /// Map the schema into the JSON document and MD5 sum under which it's registered.
+    /// Schemas are content-addressed within their format, and MD5 sums of
+    /// different formats needn't be distinct.
+    pub(super) fn content_address(
+        &self,
+        policy: CanonicalFormPolicy,
+    ) -> anyhow::Result<(serde_json::Value, String)> {
+        match self {
+            Self::Avro(schema) => content_address(schema, policy),
+            Self::Json(schema) => Ok((
+                schema.clone(),
+                format!("{:x}", md5::compute(schema.to_string())),
+            )),
+        }
+    }
+}
+
+/// RegistryId is the globally unique ID of a registered schema, tagged with its format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegistryId {
+    pub format: SchemaFormat,
+    pub id: u32,
+}
+
+/// DryRunRegistration is the outcome of resolving a schema's registry ID
+/// without registering it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DryRunRegistration {
+    /// The schema is already registered with this registry ID.
+    Found(u32),
+    /// The schema isn't registered, and would be registered with
+    /// this content-addressed MD5 and normalized document.
+    WouldRegister {
+        md5: String,
+        canonical: serde_json::Value,
+    },
+}
+
+/// SchemaRegistration is an audit record of a newly-registered schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaRegistration {
+    /// Principal which registered the schema, from Options::audit_registrant.
+    pub registrant: Option<String>,
+    pub registered_at: std::time::SystemTime,
+    /// Catalog name of the collection for which the schema was registered.
+    pub subject: String,
+    pub schema_md5: String,
+    pub registry_id: u32,
+}
+
+/// SchemaAuditSink records each new schema registration, such as to a compliance log.
+/// It's invoked only after a successful insert, and not for already-registered schemas.
+pub trait SchemaAuditSink: std::fmt::Debug + Send + Sync {
+    fn record_registration(&self, registration: SchemaRegistration);
+}
+
+/// AvroSchemaConverter is the default SchemaConverter, which uses the `avro` crate.
+#[derive(Debug, Default)]
+pub struct AvroSchemaConverter(pub avro::SchemaOptions);
+
+impl SchemaConverter for AvroSchemaConverter {
+    fn convert(
+        &self,
+        json_schema: &str,
+        key_ptr: &[doc::Pointer],
+    ) -> anyhow::Result<(avro::Schema, avro::Schema)> {
+        Ok(avro::json_schema_to_avro_with_options(
+            json_schema,
+            key_ptr,
+            &self.0,
+        )?)
+    }
+}
+
+/// RegistrationLimits bounds the new schemas registered for each collection
+/// within a sliding window, using the times of its recent registrations.
+pub(super) struct RegistrationLimits {
+    limit: usize,
+    window: std::time::Duration,
+    registered: BTreeMap<String, std::collections::VecDeque<std::time::Instant>>,
+}
+
+impl RegistrationLimits {
+    pub(super) fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            window: std::time::Duration::from_secs(60),
+            registered: BTreeMap::new(),
+        }
+    }
+
+    /// Admit `count` new registrations of `catalog_name` at `now`, returning
+    /// false (and admitting none) if they'd exceed the limit of the window.
+    fn admit(&mut self, catalog_name: &str, count: usize, now: std::time::Instant) -> bool {
+        let window = self.window;
+        let registered = self.registered.entry(catalog_name.to_string()).or_default();
+
+        while matches!(registered.front(), Some(at) if now.duration_since(*at) >= window) {
+            registered.pop_front();
+        }
+        if registered.len() + count > self.limit {
+            return false;
+        }
+        registered.extend(std::iter::repeat(now).take(count));
+        true
+    }
+}
+
+/// Admit `count` new schema registrations of `catalog_name`, or fail with
+/// TopologyError::RegistrationRateLimited if they'd exceed the rate limit.
+fn registration_permit(catalog_name: &str, count: usize) -> anyhow::Result<()> {
+    let mut limits = registration_limits().lock().unwrap();
+
+    if limits.admit(catalog_name, count, std::time::Instant::now()) {
+        return Ok(());
+    }
+    tracing::warn!(
+        collection = catalog_name,
+        limit = limits.limit,
+        "collection exceeded its rate limit of new schema registrations"
+    );
+    Err(TopologyError::RegistrationRateLimited {
+        collection: catalog_name.to_string(),
+        limit: limits.limit,
+    }
+    .into())
+}
+
+/// RegisteredSchema is a row of `registered_avro_schemas`, by its content address.
+#[derive(Debug, serde::Deserialize)]
+struct RegisteredSchema {
+    avro_schema_md5: String,
+    registry_id: u32,
+}
+
+/// Build the row which registers content-addressed `schema` for `catalog_name`.
+pub(super) fn registration_row(
+    catalog_name: &str,
+    format: SchemaFormat,
+    schema: &serde_json::Value,
+) -> serde_json::Value {
+    serde_json::json!({
+        "avro_schema": schema,
+        "catalog_name": catalog_name,
+        "schema_type": format.schema_type(),
+    })
+}
+
+/// Build the body of an insert of all `addressed` schemas which aren't `registered`,
+/// or return None if all are registered.
+fn unregistered_schemas(
+    catalog_name: &str,
+    format: SchemaFormat,
+    addressed: &[(serde_json::Value, String)],
+    registered: &[RegisteredSchema],
+) -> Option<String> {
+    let mut seen = std::collections::BTreeSet::new();
+
+    let inserts: Vec<_> = addressed
+        .iter()
+        .filter(|(_, md5)| !registered.iter().any(|row| &row.avro_schema_md5 == md5))
+        .filter(|(_, md5)| seen.insert(md5.as_str()))
+        .map(|(schema, _)| registration_row(catalog_name, format, schema))
+        .collect();
+
+    if inserts.is_empty() {
+        None
+    } else {
+        Some(serde_json::Value::Array(inserts).to_string())
+    }
+}
+
+/// Map each of `schema_md5s` into its registry ID of `rows`.
+fn map_registry_ids(schema_md5s: &[&str], rows: &[RegisteredSchema]) -> anyhow::Result<Vec<u32>> {
+    schema_md5s
+        .iter()
+        .map(|md5| {
+            rows.iter()
+                .find(|row| row.avro_schema_md5 == *md5)
+                .map(|row| row.registry_id)
+                .with_context(|| format!("schema {md5} was not registered"))
+        })
+        .collect()
+}
+
+/// Map the `result` of resolving the registry ID of a `kind` schema of `catalog_name`
+/// per `policy`. Under RegistryFailurePolicy::Degrade, a failure is logged and the
+/// schema is identified by UNREGISTERED_SCHEMA_ID.
+fn degraded_schema_id(
+    catalog_name: &str,
+    kind: telemetry::SchemaKind,
+    format: SchemaFormat,
+    result: anyhow::Result<RegistryId>,
+    policy: RegistryFailurePolicy,
+) -> anyhow::Result<RegistryId> {
+    match (result, policy) {
+        (Ok(id), _) => Ok(id),
+        (Err(err), RegistryFailurePolicy::Fail) => Err(err),
+        (Err(err), RegistryFailurePolicy::Degrade) => {
+            tracing::warn!(
+                catalog_name,
+                kind = kind.as_str(),
+                ?err,
+                "failed to resolve schema registry ID; serving records with an unregistered schema ID"
+            );
+            Ok(RegistryId {
+                format,
+                id: UNREGISTERED_SCHEMA_ID,
+            })
+        }
+    }
+}
+
+/// Map an Avro schema into the JSON document and MD5 sum under which it's registered,
+/// as normalized by the process-wide SchemaNormalizer.
+pub(super) fn content_address(
+    schema: &avro::Schema,
+    policy: CanonicalFormPolicy,
+) -> anyhow::Result<(serde_json::Value, String)> {
+    content_address_with(schema, policy, schema_normalizer().as_ref())
+}
+
+pub(super) fn content_address_with(
+    schema: &avro::Schema,
+    policy: CanonicalFormPolicy,
+    normalizer: &dyn SchemaNormalizer,
+) -> anyhow::Result<(serde_json::Value, String)> {
+    content_address_full(
+        normalizer.normalize(schema),
+        || serde_json::from_str(&schema.canonical_form()),
+        policy,
+    )
+}
+
+pub(super) fn content_address_full(
+    full: serde_json::Result<serde_json::Value>,
+    canonical: impl FnOnce() -> serde_json::Result<serde_json::Value>,
+    policy: CanonicalFormPolicy,
+) -> anyhow::Result<(serde_json::Value, String)> {
+    // Note the canonical form of the schema strips away some important metadata
+    // that we require while encoding, such as default values, and the FullForm
+    // normalizer instead registers the full form.
+    // It's fully sufficient for readers, though, and is the default normalization,
+    // as well as the fallback if the full form fails to serialize under
+    // CanonicalFormPolicy::FallbackToRaw.
+    // We map into a serde_json::Value to ensure stability of property order when content-summing.
+    let schema: serde_json::Value = match (full, policy) {
+        (Ok(schema), _) => schema,
+        (Err(err), CanonicalFormPolicy::Strict) => {
+            return Err(anyhow::Error::new(err).context("failed to serialize Avro schema"));
+        }
+        (Err(err), CanonicalFormPolicy::FallbackToRaw) => {
+            tracing::warn!(%err, "failed to serialize Avro schema; using its canonical form");
+            canonical().context("Avro canonical form is not valid JSON")?
+        }
+    };
+    let schema_md5 = format!("{:x}", md5::compute(schema.to_string()));
+
+    Ok((schema, schema_md5))
+}
+
+impl Collection {
+    /// Map the collection's key and value registry schemas into globally unique registry IDs.
+    /// This will content-address each schema to fetch a current registry ID if one is available,
+    /// or will register a new schema if not.
+    #[tracing::instrument(level = "info", skip_all, fields(collection = %self.spec.name))]
+    pub async fn registered_schema_ids(
+        &self,
+        client: &postgrest::Postgrest,
+    ) -> anyhow::Result<(RegistryId, RegistryId)> {
+        let (options, (key_schema, value_schema)) = (&self.options, &self.registry_schemas);
+
+        let resolve = |schema, kind| async move {
+            let result =
+                Self::registered_schema_id(client, &self.spec.name, schema, kind, options).await;
+            degraded_schema_id(
+                &self.spec.name,
+                kind,
+                RegistrySchema::format(schema),
+                result,
+                options.registry_failure,
+            )
+        };
+        let (key_id, value_id) = futures::try_join!(
+            resolve(key_schema, telemetry::SchemaKind::Key),
+            resolve(value_schema, telemetry::SchemaKind::Value),
+        )?;
+        Ok((key_id, value_id))
+    }
+
+    /// Resolve the registry IDs of the collection's (key, value) schemas as does
+    /// `registered_schema_ids`, but without registering schemas which aren't yet
+    /// registered. Such schemas are returned as DryRunRegistration::WouldRegister.
+    pub async fn registered_schema_ids_dry_run(
+        &self,
+        client: &postgrest::Postgrest,
+    ) -> anyhow::Result<(DryRunRegistration, DryRunRegistration)> {
+        let (options, (key_schema, value_schema)) = (&self.options, &self.registry_schemas);
+
+        futures::try_join!(
+            Self::dry_run_schema_id(client, &self.spec.name, key_schema, options),
+            Self::dry_run_schema_id(client, &self.spec.name, value_schema, options),
+        )
+    }
+
+    /// Spawn a task which revalidates `ids` every `interval`, for as long as
+    /// `ids` remains referenced elsewhere. If a registry ID no longer exists,
+    /// or no longer identifies its schema, the schema is re-resolved
+    /// (and re-registered, if needed) and `ids` are updated.
+    pub fn spawn_schema_id_revalidation(
+        &self,
+        client: postgrest::Postgrest,
+        ids: &Arc<SchemaIds>,
+        interval: std::time::Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let ids = Arc::downgrade(ids);
+        let catalog_name = self.spec.name.clone();
+        let schemas = self.registry_schemas.clone();
+        let policy = self.options.canonical_form;
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let Some(ids) = ids.upgrade() else {
+                    return; // The read has completed.
+                };
+                if let Err(err) =
+                    Self::revalidate_schema_ids(&client, &catalog_name, &schemas, &ids, policy)
+                        .await
+                {
+                    tracing::warn!(catalog_name, ?err, "failed to revalidate schema IDs");
+                }
+            }
+        })
+    }
+
+    /// Check that `ids` still identify the (key, value) `schemas` of `catalog_name`,
+    /// re-resolving and updating them if not. Returns true if `ids` were updated.
+    async fn revalidate_schema_ids(
+        client: &postgrest::Postgrest,
+        catalog_name: &str,
+        (key_schema, value_schema): &(RegistrySchema, RegistrySchema),
+        ids: &SchemaIds,
+        policy: CanonicalFormPolicy,
+    ) -> anyhow::Result<bool> {
+        let (key_id, value_id) = ids.get();
+
+        let addressed = [
+            key_schema.content_address(policy)?,
+            value_schema.content_address(policy)?,
+        ];
+        let registry_ids = [key_id.to_string(), value_id.to_string()];
+
+        let request = client
+            .from("registered_avro_schemas")
+            .in_("registry_id", &registry_ids)
+            .eq("schema_type", key_schema.format().schema_type())
+            .select("avro_schema_md5,registry_id");
+
+        let rows: Vec<RegisteredSchema> =
+            control_plane_query("revalidate_schema_ids", catalog_name, request)
+                .await
+                .context("querying for registered schema IDs")?;
+
+        let valid = |id: u32, md5: &str| {
+            rows.iter()
+                .any(|row| row.registry_id == id && row.avro_schema_md5 == md5)
+        };
+        if valid(key_id, &addressed[0].1) && valid(value_id, &addressed[1].1) {
+            return Ok(false);
+        }
+
+        let resolved = Self::registered_schema_ids_batch(
+            client,
+            catalog_name,
+            &[key_schema, value_schema],
+            policy,
+        )
+        .await?;
+        let resolved = (resolved[0], resolved[1]);
+
+        tracing::warn!(
+            catalog_name,
+            ?resolved,
+            previous = ?(key_id, value_id),
+            "registered schema IDs have changed"
+        );
+        ids.set(resolved);
+
+        Ok(true)
+    }
+
+    /// Map many schemas of `catalog_name` into globally unique registry IDs,
+    /// as does `registered_schema_ids`, but using a single query for all
+    /// already-registered schemas and a single insert of all others.
+    /// All `schemas` must be of the same SchemaFormat.
+    pub async fn registered_schema_ids_batch(
+        client: &postgrest::Postgrest,
+        catalog_name: &str,
+        schemas: &[&RegistrySchema],
+        policy: CanonicalFormPolicy,
+    ) -> anyhow::Result<Vec<u32>> {
+        let Some(format) = schemas.first().map(|schema| schema.format()) else {
+            return Ok(Vec::new());
+        };
+        if schemas.iter().any(|schema| schema.format() != format) {
+            anyhow::bail!("batched schemas of {catalog_name} must have a single format");
+        }
+        let addressed = schemas
+            .iter()
+            .map(|schema| schema.content_address(policy))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .with_context(|| format!("failed to content-address schema of {catalog_name}"))?;
+        let schema_md5s: Vec<&str> = addressed.iter().map(|(_, md5)| md5.as_str()).collect();
+        let _registry_permit = SCHEMA_REGISTRY_PERMITS
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+
+        let mut rows =
+            Self::select_registered_schemas(client, catalog_name, format, &schema_md5s).await?;
+
+        if let Some(body) = unregistered_schemas(catalog_name, format, &addressed, &rows) {
+            let unregistered: std::collections::BTreeSet<&str> = schema_md5s
+                .iter()
+                .copied()
+                .filter(|md5| !rows.iter().any(|row| row.avro_schema_md5 == *md5))
+                .collect();
+            () = registration_permit(catalog_name, unregistered.len())?;
+
+            // As with `registered_schema_id`, a concurrent registration may also insert
+            // some of these schemas, and a retried insert which had actually succeeded
+            // inserts them again. Either way, the inserted IDs are valid IDs of their schemas.
+            let request = client.from("registered_avro_schemas").insert(body);
+            let inserted: Vec<RegisteredSchema> =
+                control_plane_query("register_schemas", catalog_name, request)
+                    .await
+                    .context("inserting new registered schemas")?;
+
+            for RegisteredSchema {
+                avro_schema_md5,
+                registry_id,
+            } in &inserted
+            {
+                tracing::info!(
+                    schema_md5 = avro_schema_md5,
+                    registry_id,
+                    schema_type = format.schema_type(),
+                    "registered new schema"
+                );
+            }
+            rows.extend(inserted);
+        }
+
+        map_registry_ids(&schema_md5s, &rows)
+    }
+
+    /// Select the registry IDs of whichever of `schema_md5s` are already registered,
+    /// earliest first.
+    async fn select_registered_schemas(
+        client: &postgrest::Postgrest,
+        catalog_name: &str,
+        format: SchemaFormat,
+        schema_md5s: &[&str],
+    ) -> anyhow::Result<Vec<RegisteredSchema>> {
+        let request = client
+            .from("registered_avro_schemas")
+            .in_("avro_schema_md5", schema_md5s)
+            .eq("schema_type", format.schema_type())
+            .order("registry_id.asc")
+            .select("avro_schema_md5,registry_id");
+
+        control_plane_query("lookup_registered_schemas", catalog_name, request)
+            .await
+            .context("querying for already-registered schemas")
+    }
+
+    async fn registered_schema_id(
+        client: &postgrest::Postgrest,
+        catalog_name: &str,
+        schema: &RegistrySchema,
+        kind: telemetry::SchemaKind,
+        options: &Options,
+    ) -> anyhow::Result<RegistryId> {
+        #[derive(serde::Deserialize)]
+        struct Row {
+            registry_id: u32,
+        }
+        let format = schema.format();
+        let (schema, schema_md5) = schema
+            .content_address(options.canonical_form)
+            .with_context(|| {
+                format!(
+                    "failed to content-address {} schema of {catalog_name}",
+                    kind.as_str()
+                )
+            })?;
+        let tagged = |id| RegistryId { format, id };
+
+        if let Some(cache) = &options.schema_id_cache {
+            if let Some(id) = cache.get(format, &schema_md5) {
+                return Ok(id);
+            }
+        }
+        let cached = |id: RegistryId| {
+            if let Some(cache) = &options.schema_id_cache {
+                cache.insert(&schema_md5, id);
+            }
+            id
+        };
+        let _registry_permit = SCHEMA_REGISTRY_PERMITS
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+
+        // Registered schemas are looked up by their hex MD5 alone, which never needs
+        // escaping within a filter. The `catalog_name` is only ever sent within the
+        // JSON body of an insert, so it may hold any characters.
+        let lookup = Self::lookup_schema_id(client, catalog_name, format, &schema_md5);
+        if let Some(registry_id) = telemetry::observe_schema_lookup(kind, lookup).await? {
+            if options.audit_schema_collisions {
+                let audit = Self::audit_schema_collision(
+                    client,
+                    catalog_name,
+                    &schema_md5,
+                    &schema,
+                    registry_id,
+                );
+                if let Err(err) = audit.await {
+                    tracing::warn!(
+                        ?err,
+                        catalog_name,
+                        registry_id,
+                        "failed to audit registered schema"
+                    );
+                }
+            }
+            return Ok(cached(tagged(registry_id)));
+        }
+        if options.registry_read_only {
+            return Err(TopologyError::RegistryReadOnly {
+                collection: catalog_name.to_string(),
+                schema_md5,
+            }
+            .into());
+        }
+
+        if options.schema_compatibility == SchemaCompatibilityPolicy::Backward
+            && format == SchemaFormat::Avro
+        {
+            let registered: Vec<serde_json::Value> =
+                list_registered_schemas(client, catalog_name, Some(format))
+                    .await?
+                    .into_iter()
+                    .rev() // Newest first.
+                    .map(|(_registry_id, registered)| registered)
+                    .collect();
+            () = check_backward_compatible(catalog_name, kind, &registered, &schema)?;
+        }
+        () = registration_permit(catalog_name, 1)?;
+
+        // Registered schemas aren't unique by MD5, so concurrent registrations of a schema
+        // may each insert it. Each of their IDs is a valid ID of the schema, and later
+        // lookups resolve to the earliest of them.
+        let body = serde_json::json!([registration_row(catalog_name, format, &schema)]).to_string();
+        let request = client.from("registered_avro_schemas").insert(body);
+
+        let mut rows: Vec<Row> = control_plane_query("register_schema", catalog_name, request)
+            .await
+            .context("inserting new registered schema")?;
+
+        let Some(Row { registry_id }) = rows.pop() else {
+            anyhow::bail!(
+                "control plane returned no row for the new {} schema {schema_md5} of {catalog_name}",
+                kind.as_str()
+            );
+        };
+        tracing::info!(
+            schema_md5,
+            registry_id,
+            schema_type = format.schema_type(),
+            "registered new schema"
+        );
+        let id = cached(tagged(registry_id));
+
+        if let Some(sink) = &options.audit_sink {
+            sink.record_registration(SchemaRegistration {
+                registrant: options.audit_registrant.clone(),
+                registered_at: std::time::SystemTime::now(),
+                subject: catalog_name.to_string(),
+                schema_md5,
+                registry_id,
+            });
+        }
+        Ok(id)
+    }
+
+    /// Resolve the registry IDs of the collection's current key and value schemas,
+    /// without registering them. Returns None if either isn't yet registered.
+    /// This is the read-only counterpart of `registered_schema_ids`.
+    pub async fn current_schema_ids(
+        &self,
+        client: &postgrest::Postgrest,
+    ) -> anyhow::Result<Option<(RegistryId, RegistryId)>> {
+        let (policy, (key_schema, value_schema)) =
+            (self.options.canonical_form, &self.registry_schemas);
+
+        let (key_id, value_id) = futures::try_join!(
+            Self::current_schema_id(client, &self.spec.name, key_schema, policy),
+            Self::current_schema_id(client, &self.spec.name, value_schema, policy),
+        )?;
+        Ok(key_id.zip(value_id))
+    }
+
+    async fn current_schema_id(
+        client: &postgrest::Postgrest,
+        catalog_name: &str,
+        schema: &RegistrySchema,
+        policy: CanonicalFormPolicy,
+    ) -> anyhow::Result<Option<RegistryId>> {
+        let format = schema.format();
+        let (_schema, schema_md5) = schema
+            .content_address(policy)
+            .with_context(|| format!("failed to content-address schema of {catalog_name}"))?;
+
+        Ok(
+            Self::lookup_schema_id(client, catalog_name, format, &schema_md5)
+                .await?
+                .map(|id| RegistryId { format, id }),
+        )
+    }
+
+    /// Look up the registry ID of an already-registered schema of `format` by its content address.
+    async fn dry_run_schema_id(
+        client: &postgrest::Postgrest,
+        catalog_name: &str,
+        schema: &RegistrySchema,
+        options: &Options,
+    ) -> anyhow::Result<DryRunRegistration> {
+        let format = schema.format();
+        let (canonical, md5) = schema
+            .content_address(options.canonical_form)
+            .with_context(|| format!("failed to content-address schema of {catalog_name}"))?;
+
+        Ok(
+            match Self::lookup_schema_id(client, catalog_name, format, &md5).await? {
+                Some(registry_id) => DryRunRegistration::Found(registry_id),
+                None => DryRunRegistration::WouldRegister { md5, canonical },
+            },
+        )
+    }
+
+    async fn lookup_schema_id(
+        client: &postgrest::Postgrest,
+        catalog_name: &str,
+        format: SchemaFormat,
+        schema_md5: &str,
+    ) -> anyhow::Result<Option<u32>> {
+        #[derive(serde::Deserialize)]
+        struct Row {
+            registry_id: u32,
+        }
+        let request = client
+            .from("registered_avro_schemas")
+            .eq("avro_schema_md5", schema_md5)
+            .eq("schema_type", format.schema_type())
+            .order("registry_id.asc")
+            .limit(1)
+            .select("registry_id");
+
+        let mut rows: Vec<Row> =
+            control_plane_query("lookup_registered_schema", catalog_name, request)
+                .await
+                .context("querying for an already-registered schema")?;
+
+        Ok(rows.pop().map(|Row { registry_id }| registry_id))
+    }
+
+    /// Verify that the schema registered as `registry_id`, which was found by
+    /// `schema_md5`, is the content-addressed `schema` of `catalog_name`.
+    /// If it's not, the MD5s of distinct schemas have collided: a warning is
+    /// logged with both catalog names, and the stored catalog name is returned.
+    async fn audit_schema_collision(
+        client: &postgrest::Postgrest,
+        catalog_name: &str,
+        schema_md5: &str,
+        schema: &serde_json::Value,
+        registry_id: u32,
+    ) -> anyhow::Result<Option<String>> {
+        #[derive(serde::Deserialize)]
+        struct Row {
+            avro_schema: serde_json::Value,
+            catalog_name: String,
+        }
+        let request = client
+            .from("registered_avro_schemas")
+            .eq("registry_id", registry_id.to_string())
+            .select("avro_schema,catalog_name");
+
+        let rows: Vec<Row> = control_plane_query("audit_registered_schema", catalog_name, request)
+            .await
+            .context("querying for a registered schema to audit")?;
+
+        let Some(Row {
+            avro_schema,
+            catalog_name: stored_catalog_name,
+        }) = rows.into_iter().next()
+        else {
+            return Ok(None);
+        };
+        if &avro_schema == schema {
+            return Ok(None);
+        }
+
+        tracing::warn!(
+            catalog_name,
+            stored_catalog_name,
+            registry_id,
+            schema_md5,
+            "registered schema has the MD5 of, but differs from, the schema looked up"
+        );
+        Ok(Some(stored_catalog_name))
+    }
+}
+
+/// Check that records written with the new Avro `schema` of `kind` can be read
+/// by readers of the newest schema of `kind` within `registered` (newest first).
+/// A first schema of its kind is always compatible.
+fn check_backward_compatible(
+    catalog_name: &str,
+    kind: telemetry::SchemaKind,
+    registered: &[serde_json::Value],
+    schema: &serde_json::Value,
+) -> anyhow::Result<()> {
+    let is_key = kind == telemetry::SchemaKind::Key;
+
+    let Some(previous) = registered
+        .iter()
+        .find(|previous| is_registered_key_schema(SchemaFormat::Avro, previous) == is_key)
+    else {
+        return Ok(());
+    };
+    let previous = avro::Schema::parse(previous)
+        .with_context(|| format!("parsing previous registered schema of {catalog_name}"))?;
+    let schema = avro::Schema::parse(schema)
+        .with_context(|| format!("parsing new schema of {catalog_name}"))?;
+
+    if !avro::can_read(&schema, &previous) {
+        tracing::warn!(
+            catalog_name,
+            kind = kind.as_str(),
+            "new schema is incompatible with its previous registered schema"
+        );
+        return Err(TopologyError::SchemaIncompatible {
+            collection: catalog_name.to_string(),
+            kind: kind.as_str().to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::topology::test::{collection_fixture, fake_postgrest, spec_fixture};
+    use crate::topology::{kafka_error, CanonicalForm, FullForm, SchemaIdCache};
+    use kafka_protocol::error::ResponseError;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_content_address_full_form() {
+        let canonical = || Ok(json!({"type": "string"}));
+        let failed = || Err(serde_json::from_str::<serde_json::Value>("{not json").unwrap_err());
+
+        // A serialized full form is used regardless of policy.
+        let (schema, schema_md5) = content_address_full(
+            Ok(json!({"type": "long", "doc": "full"})),
+            canonical,
+            CanonicalFormPolicy::Strict,
+        )
+        .unwrap();
+        assert_eq!(schema, json!({"type": "long", "doc": "full"}));
+        assert_eq!(
+            schema_md5,
+            format!("{:x}", md5::compute(r#"{"doc":"full","type":"long"}"#))
+        );
+
+        // A failed serialization is an error (and not a panic) under the strict policy.
+        let err =
+            content_address_full(failed(), canonical, CanonicalFormPolicy::Strict).unwrap_err();
+        assert!(format!("{err:#}").contains("failed to serialize"));
+
+        // Or, is content-addressed by its canonical form under the fallback policy.
+        let (schema, schema_md5) =
+            content_address_full(failed(), canonical, CanonicalFormPolicy::FallbackToRaw).unwrap();
+        assert_eq!(schema, json!({"type": "string"}));
+        assert_eq!(
+            schema_md5,
+            format!("{:x}", md5::compute(r#"{"type":"string"}"#))
+        );
+
+        // Under the full form, schemas which differ only in defaults have
+        // distinct addresses, though their canonical forms are the same.
+        let schema = |default: serde_json::Value| {
+            let json_schema = json!({
+                "type": "object",
+                "properties": {
+                    "id": {"type": "string"},
+                    "n": {"type": "integer", "default": default},
+                },
+                "required": ["id"],
+            });
+            avro::json_schema_to_avro(&json_schema.to_string(), &[doc::Pointer::from_str("/id")])
+                .unwrap()
+                .1
+        };
+        let (one, two) = (schema(json!(1)), schema(json!(2)));
+        assert_eq!(one.canonical_form(), two.canonical_form());
+
+        let (one_doc, one_md5) =
+            content_address_with(&one, CanonicalFormPolicy::Strict, &FullForm).unwrap();
+        let (_, two_md5) =
+            content_address_with(&two, CanonicalFormPolicy::Strict, &FullForm).unwrap();
+        assert_ne!(one_md5, two_md5);
+        assert_eq!(one_doc, serde_json::to_value(&one).unwrap());
+
+        // The canonical form is the default normalization, under which they share an address.
+        let (one_doc, one_md5) = content_address(&one, CanonicalFormPolicy::Strict).unwrap();
+        let (_, two_md5) = content_address(&two, CanonicalFormPolicy::Strict).unwrap();
+        assert_eq!(one_md5, two_md5);
+        assert_eq!(
+            one_doc,
+            serde_json::from_str::<serde_json::Value>(&one.canonical_form()).unwrap()
+        );
+        let canonical =
+            content_address_with(&one, CanonicalFormPolicy::Strict, &CanonicalForm).unwrap();
+        assert_eq!(canonical, (one_doc, one_md5));
+    }
+
+    #[test]
+    fn test_subject_name_strategies() {
+        use SubjectNameStrategy::*;
+
+        let key = RegistrySchema::Avro(
+            avro::Schema::parse(&json!({
+                "type": "record",
+                "name": "Key",
+                "namespace": "root",
+                "fields": [],
+            }))
+            .unwrap(),
+        );
+        assert_eq!(key.record_name().as_deref(), Some("root.Key"));
+        assert_eq!(RegistrySchema::Json(json!({})).record_name(), None);
+
+        for (strategy, is_key, record_name, expect) in [
+            (TopicName, true, Some("root.Key"), "acmeCo_things-key"),
+            (TopicName, false, Some("root"), "acmeCo_things-value"),
+            (RecordName, true, Some("root.Key"), "root.Key"),
+            (RecordName, false, None, "acmeCo_things-value"),
+            (TopicRecordName, false, Some("root"), "acmeCo_things-root"),
+            (TopicRecordName, true, None, "acmeCo_things-key"),
+        ] {
+            let subject = strategy.subject("acmeCo_things", is_key, record_name);
+            assert_eq!(subject, expect);
+
+            // Subjects parse back into their topic and schema.
+            let (topic, schema) = strategy.parse_subject(&subject).unwrap();
+            let expect_schema = match (strategy, record_name) {
+                (TopicName, _) | (_, None) if is_key => SubjectSchema::Key,
+                (TopicName, _) | (_, None) => SubjectSchema::Value,
+                (_, Some(record_name)) => SubjectSchema::Record(record_name),
+            };
+            assert_eq!(schema, expect_schema);
+
+            let expect_topic =
+                (strategy != RecordName || record_name.is_none()).then_some("acmeCo_things");
+            assert_eq!(topic, expect_topic);
+        }
+
+        assert!(TopicName.parse_subject("acmeCo_things").is_err());
+        assert!(TopicRecordName.parse_subject("root").is_err());
+        assert_eq!(
+            "topic-record-name".parse::<SubjectNameStrategy>().unwrap(),
+            TopicRecordName
+        );
+        assert!("other".parse::<SubjectNameStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_batched_schema_registration() {
+        let addressed: Vec<_> = ["long", "string", "boolean"]
+            .iter()
+            .map(|type_| {
+                content_address_full(
+                    Ok(json!({ "type": type_ })),
+                    || unreachable!(),
+                    CanonicalFormPolicy::Strict,
+                )
+                .unwrap()
+            })
+            .collect();
+        let schema_md5s: Vec<&str> = addressed.iter().map(|(_, md5)| md5.as_str()).collect();
+
+        // All three new schemas are inserted in one call.
+        let body =
+            unregistered_schemas("acmeCo/collection", SchemaFormat::Avro, &addressed, &[]).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body,
+            json!([
+                {"avro_schema": {"type": "long"}, "catalog_name": "acmeCo/collection", "schema_type": "AVRO"},
+                {"avro_schema": {"type": "string"}, "catalog_name": "acmeCo/collection", "schema_type": "AVRO"},
+                {"avro_schema": {"type": "boolean"}, "catalog_name": "acmeCo/collection", "schema_type": "AVRO"},
+            ])
+        );
+
+        // Inserted rows are mapped back to schemas by content address, regardless of order.
+        let rows: Vec<RegisteredSchema> = [(2, 30), (0, 10), (1, 20)]
+            .into_iter()
+            .map(|(index, registry_id)| RegisteredSchema {
+                avro_schema_md5: schema_md5s[index].to_string(),
+                registry_id,
+            })
+            .collect();
+        assert_eq!(
+            map_registry_ids(&schema_md5s, &rows).unwrap(),
+            vec![10, 20, 30]
+        );
+
+        // Nothing is inserted if all schemas are already registered.
+        assert!(
+            unregistered_schemas("acmeCo/collection", SchemaFormat::Avro, &addressed, &rows)
+                .is_none()
+        );
+        // A schema having no row is an error.
+        assert!(map_registry_ids(&schema_md5s, &rows[..2]).is_err());
+    }
+
+    #[test]
+    fn test_registration_limits() {
+        let mut limits = RegistrationLimits::new(3);
+        let start = std::time::Instant::now();
+        let at = |secs| start + std::time::Duration::from_secs(secs);
+
+        assert!(limits.admit("acmeCo/things", 1, at(0)));
+        assert!(limits.admit("acmeCo/things", 2, at(10)));
+        // The limit is exceeded, and a rejected batch admits none of its registrations.
+        assert!(!limits.admit("acmeCo/things", 1, at(20)));
+        assert!(!limits.admit("acmeCo/things", 2, at(65)));
+        // Limits are per-collection.
+        assert!(limits.admit("acmeCo/others", 3, at(20)));
+
+        // Registrations leave the window after a minute.
+        assert!(limits.admit("acmeCo/things", 1, at(60)));
+        assert!(!limits.admit("acmeCo/things", 1, at(69)));
+        assert!(limits.admit("acmeCo/things", 2, at(70)));
+    }
+
+    #[test]
+    fn test_schema_converter() {
+        #[derive(Debug)]
+        struct Renaming;
+
+        impl SchemaConverter for Renaming {
+            fn convert(
+                &self,
+                json_schema: &str,
+                key_ptr: &[doc::Pointer],
+            ) -> anyhow::Result<(avro::Schema, avro::Schema)> {
+                let (key, value) = AvroSchemaConverter::default().convert(json_schema, key_ptr)?;
+
+                let avro::Schema::Record(mut record) = value else {
+                    anyhow::bail!("expected a record");
+                };
+                record.name = avro::RecordName::new("acmeCo.Thing").unwrap();
+                Ok((key, avro::Schema::Record(record)))
+            }
+        }
+
+        let spec = spec_fixture();
+        let key_ptr: Vec<doc::Pointer> =
+            spec.key.iter().map(|p| doc::Pointer::from_str(p)).collect();
+        let record_name = |options: &Options| {
+            let (_key, value) = options
+                .schema_converter()
+                .convert(&spec.write_schema_json, &key_ptr)
+                .unwrap();
+            let avro::Schema::Record(record) = value else {
+                panic!("expected a record");
+            };
+            record.name.fullname(None)
+        };
+
+        assert_eq!(record_name(&Options::default()), "root");
+        assert_eq!(
+            record_name(&Options::default().with_schema_converter(Arc::new(Renaming))),
+            "acmeCo.Thing"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_registered_schemas() {
+        use axum::{extract::Query, http::HeaderMap, routing::get};
+
+        // A fake PostgREST of five schemas of `acmeCo/things`, served by Range.
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let ranges_clone = ranges.clone();
+
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(
+                move |headers: HeaderMap, Query(query): Query<BTreeMap<String, String>>| {
+                    assert_eq!(query["catalog_name"], "eq.acmeCo/things");
+                    assert_eq!(query["order"], "registry_id.asc");
+                    assert_eq!(query["schema_type"], "eq.AVRO");
+
+                    let range = headers["Range"].to_str().unwrap().to_string();
+                    let (low, high) = range.split_once('-').unwrap();
+                    let (low, high): (usize, usize) = (low.parse().unwrap(), high.parse().unwrap());
+                    ranges_clone.lock().unwrap().push(range);
+
+                    let rows: Vec<_> = (low..=high.min(4))
+                        .map(|n| json!({"registry_id": 10 + n, "avro_schema": {"type": "long"}}))
+                        .collect();
+                    async move { serde_json::to_string(&rows).unwrap() }
+                },
+            ),
+        );
+        let client = fake_postgrest(router).await;
+
+        let schemas =
+            list_registered_schemas_paged(&client, "acmeCo/things", Some(SchemaFormat::Avro), 2)
+                .await
+                .unwrap();
+        assert_eq!(
+            schemas,
+            (10..15)
+                .map(|id| (id, json!({"type": "long"})))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(*ranges.lock().unwrap(), vec!["0-1", "2-3", "4-5"]);
+    }
+
+    #[test]
+    fn test_check_backward_compatible() {
+        use telemetry::SchemaKind;
+
+        let key_ptr = vec![doc::Pointer::from_str("/id")];
+        let schemas = |properties: serde_json::Value, required: serde_json::Value| {
+            let json_schema = json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            });
+            let (key, value) =
+                avro::json_schema_to_avro(&json_schema.to_string(), &key_ptr).unwrap();
+            (
+                serde_json::to_value(&key).unwrap(),
+                serde_json::to_value(&value).unwrap(),
+            )
+        };
+        let (key, previous) = schemas(
+            json!({"id": {"type": "string"}, "n": {"type": "integer"}}),
+            json!(["id", "n"]),
+        );
+        // Registered schemas are newest first, and the key was registered last.
+        let registered = vec![key.clone(), previous.clone()];
+
+        // A first schema of its kind is always compatible.
+        check_backward_compatible("acmeCo/things", SchemaKind::Value, &[], &previous).unwrap();
+        // As is an unchanged schema, or one which adds a field.
+        check_backward_compatible("acmeCo/things", SchemaKind::Value, &registered, &previous)
+            .unwrap();
+        check_backward_compatible("acmeCo/things", SchemaKind::Key, &registered, &key).unwrap();
+
+        let (_, added) = schemas(
+            json!({
+                "id": {"type": "string"},
+                "n": {"type": "integer"},
+                "extra": {"type": "boolean"},
+            }),
+            json!(["id", "n", "extra"]),
+        );
+        check_backward_compatible("acmeCo/things", SchemaKind::Value, &registered, &added).unwrap();
+
+        // Removing a field which readers require, and which has no default, is not.
+        let (_, removed) = schemas(json!({"id": {"type": "string"}}), json!(["id"]));
+        let err =
+            check_backward_compatible("acmeCo/things", SchemaKind::Value, &registered, &removed)
+                .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TopologyError>(),
+            Some(TopologyError::SchemaIncompatible { collection, kind })
+                if collection == "acmeCo/things" && kind == "value"
+        ));
+        assert_eq!(
+            kafka_error(&err).map(|e| e.code()),
+            Some(ResponseError::UnknownServerError.code())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_current_schema_ids_never_inserts() {
+        use axum::{extract::Query, routing::get, Json};
+
+        let collection = collection_fixture(spec_fixture());
+        let (_, key_md5) =
+            content_address(&collection.key_schema, CanonicalFormPolicy::Strict).unwrap();
+
+        // A fake PostgREST which knows of only the key schema, and counts inserts.
+        let inserts = Arc::new(AtomicUsize::new(0));
+        let inserts_clone = inserts.clone();
+
+        let key_filter = format!("eq.{key_md5}");
+
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(move |Query(query): Query<BTreeMap<String, String>>| {
+                let known = query.get("avro_schema_md5") == Some(&key_filter);
+                async move {
+                    if known {
+                        Json(json!([{"registry_id": 7}]))
+                    } else {
+                        Json(json!([]))
+                    }
+                }
+            })
+            .post(move || {
+                inserts_clone.fetch_add(1, Ordering::SeqCst);
+                async { Json(json!([{"registry_id": 99}])) }
+            }),
+        );
+        let client = fake_postgrest(router).await;
+
+        // The value schema isn't registered, so neither ID is returned.
+        assert_eq!(collection.current_schema_ids(&client).await.unwrap(), None);
+        assert_eq!(
+            Collection::current_schema_id(
+                &client,
+                &collection.spec.name,
+                &collection.registry_schemas.0,
+                CanonicalFormPolicy::Strict
+            )
+            .await
+            .unwrap(),
+            Some(RegistryId {
+                format: SchemaFormat::Avro,
+                id: 7
+            })
+        );
+        assert_eq!(inserts.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_revalidate_deleted_schema_id() {
+        use axum::{extract::Query, routing::get, Json};
+
+        let collection = collection_fixture(spec_fixture());
+        let policy = CanonicalFormPolicy::Strict;
+        let (_, key_md5) = content_address(&collection.key_schema, policy).unwrap();
+        let (_, value_md5) = content_address(&collection.value_schema, policy).unwrap();
+
+        // A fake PostgREST of registered schemas, where the value schema
+        // was previously registered as ID 3 but has been re-registered as 12.
+        let table = [(7, key_md5), (12, value_md5)];
+
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(move |Query(query): Query<BTreeMap<String, String>>| {
+                let in_list = |name: &str| -> Vec<String> {
+                    query
+                        .get(name)
+                        .and_then(|v| v.strip_prefix("in.(")?.strip_suffix(')'))
+                        .map(|v| v.split(',').map(str::to_string).collect())
+                        .unwrap_or_default()
+                };
+                let (ids, md5s) = (in_list("registry_id"), in_list("avro_schema_md5"));
+
+                let rows: Vec<_> = table
+                    .iter()
+                    .filter(|(id, md5)| ids.contains(&id.to_string()) || md5s.contains(md5))
+                    .map(|(id, md5)| json!({"registry_id": id, "avro_schema_md5": md5}))
+                    .collect();
+
+                async move { Json(rows) }
+            }),
+        );
+        let client = fake_postgrest(router).await;
+        let schemas = collection.registry_schemas.clone();
+        let ids = SchemaIds::new(7, 3);
+
+        // The deleted ID is re-resolved.
+        assert!(Collection::revalidate_schema_ids(
+            &client,
+            &collection.spec.name,
+            &schemas,
+            &ids,
+            policy
+        )
+        .await
+        .unwrap());
+        assert_eq!(ids.get(), (7, 12));
+
+        // Now-valid IDs are unchanged.
+        assert!(!Collection::revalidate_schema_ids(
+            &client,
+            &collection.spec.name,
+            &schemas,
+            &ids,
+            policy
+        )
+        .await
+        .unwrap());
+        assert_eq!(ids.get(), (7, 12));
+    }
+
+    #[tokio::test]
+    async fn test_register_awkward_catalog_name() {
+        use axum::{extract::Query, routing::get, Json};
+
+        let collection = collection_fixture(spec_fixture());
+        let policy = CanonicalFormPolicy::Strict;
+        let catalog_name = r#"acmeCo/my "things", v2"#;
+
+        // A fake PostgREST holding (registry_id, catalog_name, avro_schema_md5) rows,
+        // which computes the MD5 of inserted schemas as does the database.
+        let table: Arc<Mutex<Vec<(u32, String, String)>>> = Default::default();
+        let (select_table, insert_table) = (table.clone(), table.clone());
+
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(move |Query(query): Query<BTreeMap<String, String>>| {
+                let rows: Vec<_> = select_table
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|(_, _, md5)| {
+                        query.get("avro_schema_md5") == Some(&format!("eq.{md5}"))
+                    })
+                    .map(|(id, _, _)| json!({"registry_id": id}))
+                    .collect();
+                async move { Json(rows) }
+            })
+            .post(move |body: String| {
+                let body: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+                let mut table = insert_table.lock().unwrap();
+                let mut rows = Vec::new();
+
+                for row in body {
+                    let id = table.len() as u32 + 1;
+                    let md5 = format!("{:x}", md5::compute(row["avro_schema"].to_string()));
+                    let name = row["catalog_name"].as_str().unwrap().to_string();
+                    table.push((id, name, md5));
+                    rows.push(json!({"registry_id": id}));
+                }
+                async move { Json(rows) }
+            }),
+        );
+        let client = fake_postgrest(router).await;
+
+        // The first registration inserts, and the second finds the inserted row.
+        for _ in 0..2 {
+            let id = Collection::registered_schema_id(
+                &client,
+                catalog_name,
+                &collection.registry_schemas.0,
+                telemetry::SchemaKind::Key,
+                &Options::default(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(id.id, 1);
+        }
+
+        let (_, key_md5) = content_address(&collection.key_schema, policy).unwrap();
+        assert_eq!(
+            *table.lock().unwrap(),
+            vec![(1, catalog_name.to_string(), key_md5)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registered_schema_ids_dry_run() {
+        use axum::{extract::Query, routing::get, Json};
+
+        let collection = collection_fixture(spec_fixture());
+        let policy = collection.options.canonical_form;
+        let (_, key_md5) = collection
+            .registry_schemas
+            .0
+            .content_address(policy)
+            .unwrap();
+        let (value_canonical, value_md5) = collection
+            .registry_schemas
+            .1
+            .content_address(policy)
+            .unwrap();
+
+        // A fake PostgREST where only the key schema is registered,
+        // and which fails any attempt to insert a schema.
+        let select_md5 = key_md5.clone();
+        async fn insert() -> &'static str {
+            panic!("dry runs must not insert schemas")
+        }
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(move |Query(query): Query<BTreeMap<String, String>>| {
+                let rows = if query.get("avro_schema_md5") == Some(&format!("eq.{select_md5}")) {
+                    json!([{"registry_id": 12}])
+                } else {
+                    json!([])
+                };
+                async move { Json(rows) }
+            })
+            .post(insert),
+        );
+        let client = fake_postgrest(router).await;
+        let (key, value) = collection
+            .registered_schema_ids_dry_run(&client)
+            .await
+            .unwrap();
+
+        assert_eq!(key, DryRunRegistration::Found(12));
+        assert_eq!(
+            value,
+            DryRunRegistration::WouldRegister {
+                md5: value_md5,
+                canonical: value_canonical,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registry_failure_policy() {
+        use axum::{http::StatusCode, Json};
+
+        // A fake PostgREST which fails every request.
+        let router = axum::Router::new().fallback(|| async {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "unavailable"})),
+            )
+        });
+        let client = fake_postgrest(router).await;
+        let mut collection = collection_fixture(spec_fixture());
+
+        // By default, a failed resolution fails.
+        assert!(collection.registered_schema_ids(&client).await.is_err());
+
+        // When degraded, schemas are identified by the unregistered sentinel.
+        collection.options.registry_failure = RegistryFailurePolicy::Degrade;
+        let (key_id, value_id) = collection.registered_schema_ids(&client).await.unwrap();
+        assert_eq!(key_id.id, UNREGISTERED_SCHEMA_ID);
+        assert_eq!(value_id.id, UNREGISTERED_SCHEMA_ID);
+        assert_eq!(key_id.format, collection.registry_schemas.0.format());
+    }
+
+    #[tokio::test]
+    async fn test_registry_read_only() {
+        use axum::{extract::Query, routing::get, Json};
+
+        let collection = collection_fixture(spec_fixture());
+        let (_, key_md5) = collection
+            .registry_schemas
+            .0
+            .content_address(collection.options.canonical_form)
+            .unwrap();
+
+        // A fake PostgREST where only the key schema is registered,
+        // and which counts attempted inserts.
+        let inserts = Arc::new(AtomicUsize::new(0));
+        let (select_md5, inserts_clone) = (key_md5.clone(), inserts.clone());
+
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(move |Query(query): Query<BTreeMap<String, String>>| {
+                let rows = if query.get("avro_schema_md5") == Some(&format!("eq.{select_md5}")) {
+                    json!([{"registry_id": 4}])
+                } else {
+                    json!([])
+                };
+                async move { Json(rows) }
+            })
+            .post(move || {
+                inserts_clone.fetch_add(1, Ordering::SeqCst);
+                async { Json(json!([{"registry_id": 9}])) }
+            }),
+        );
+        let client = fake_postgrest(router).await;
+        let options = Options {
+            registry_read_only: true,
+            ..Default::default()
+        };
+        let register = |schema, kind| {
+            Collection::registered_schema_id(&client, "acmeCo/things", schema, kind, &options)
+        };
+
+        // A registered schema is found.
+        let id = register(&collection.registry_schemas.0, telemetry::SchemaKind::Key)
+            .await
+            .unwrap();
+        assert_eq!(id.id, 4);
+
+        // An unregistered schema is an error, and isn't inserted.
+        let err = register(&collection.registry_schemas.1, telemetry::SchemaKind::Value)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TopologyError>(),
+            Some(TopologyError::RegistryReadOnly { collection, .. }) if collection == "acmeCo/things"
+        ));
+        assert_eq!(inserts.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_schema_id_cache_skips_lookups() {
+        use axum::{routing::get, Json};
+
+        let collection = collection_fixture(spec_fixture());
+
+        // A fake PostgREST which counts lookups of registered schemas.
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let lookups_clone = lookups.clone();
+
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(move || {
+                lookups_clone.fetch_add(1, Ordering::SeqCst);
+                async { Json(json!([{"registry_id": 4}])) }
+            }),
+        );
+        let client = fake_postgrest(router).await;
+        let options = Options {
+            schema_id_cache: Some(Arc::new(SchemaIdCache::new(16))),
+            ..Default::default()
+        };
+        let register = || {
+            Collection::registered_schema_id(
+                &client,
+                "acmeCo/things",
+                &collection.registry_schemas.0,
+                telemetry::SchemaKind::Key,
+                &options,
+            )
+        };
+
+        // Only the first resolution of the schema queries the registry.
+        assert_eq!(register().await.unwrap().id, 4);
+        assert_eq!(register().await.unwrap().id, 4);
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+        assert_eq!(options.schema_id_cache.as_ref().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_registered_schema_id_without_inserted_row() {
+        use axum::{routing::get, Json};
+
+        let collection = collection_fixture(spec_fixture());
+
+        // A fake PostgREST which has no registered schemas,
+        // and which returns no row for an insert.
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(|| async { Json(json!([])) }).post(|| async { Json(json!([])) }),
+        );
+        let client = fake_postgrest(router).await;
+        let err = Collection::registered_schema_id(
+            &client,
+            "acmeCo/things",
+            &collection.registry_schemas.1,
+            telemetry::SchemaKind::Value,
+            &Options::default(),
+        )
+        .await
+        .unwrap_err();
+
+        // The missing row is an error rather than a panic.
+        let message = err.to_string();
+        assert!(message.starts_with("control plane returned no row for the new value schema"));
+        assert!(message.ends_with("of acmeCo/things"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_schema_collision() {
+        use axum::{extract::Query, routing::get, Json};
+
+        // A fake PostgREST where registry ID 4 holds a schema of another collection.
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(|Query(query): Query<BTreeMap<String, String>>| async move {
+                if query.get("registry_id").map(String::as_str) == Some("eq.4") {
+                    Json(json!([{"avro_schema": {"type": "string"}, "catalog_name": "otherCo/things"}]))
+                } else {
+                    Json(json!([]))
+                }
+            }),
+        );
+        let client = &fake_postgrest(router).await;
+        let audit = |schema: serde_json::Value, registry_id| async move {
+            Collection::audit_schema_collision(
+                client,
+                "acmeCo/things",
+                "0123abcd",
+                &schema,
+                registry_id,
+            )
+            .await
+            .unwrap()
+        };
+
+        // A stored schema which equals the looked-up schema isn't a collision.
+        assert_eq!(audit(json!({"type": "string"}), 4).await, None);
+        // A stored schema which differs from it is, and names the stored collection.
+        assert_eq!(
+            audit(json!({"type": "long"}), 4).await,
+            Some("otherCo/things".to_string())
+        );
+        // A registry ID which isn't found can't be audited.
+        assert_eq!(audit(json!({"type": "long"}), 5).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_batched_registration() {
+        use axum::{http::StatusCode, routing::get, Json};
+
+        let schemas: Vec<RegistrySchema> = ["long", "string"]
+            .iter()
+            .map(|type_| RegistrySchema::Avro(avro::Schema::parse(&json!(type_)).unwrap()))
+            .collect();
+        let md5s: Vec<String> = schemas
+            .iter()
+            .map(|schema| {
+                schema
+                    .content_address(CanonicalFormPolicy::Strict)
+                    .unwrap()
+                    .1
+            })
+            .collect();
+
+        // A fake PostgREST where the first schema is already registered.
+        let inserts = Arc::new(AtomicUsize::new(0));
+        let inserts_clone = inserts.clone();
+        let (md5s_get, md5s_post) = (md5s.clone(), md5s.clone());
+
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(move || {
+                let rows = json!([{"avro_schema_md5": md5s_get[0], "registry_id": 5}]);
+                async move { Json(rows) }
+            })
+            .post(move |Json(body): Json<Vec<serde_json::Value>>| {
+                inserts_clone.fetch_add(1, Ordering::SeqCst);
+                let md5 = md5s_post[1].clone();
+                async move {
+                    // Only the schema which isn't registered is inserted.
+                    assert_eq!(body.len(), 1);
+                    (
+                        StatusCode::CREATED,
+                        Json(json!([{"avro_schema_md5": md5, "registry_id": 6}])),
+                    )
+                }
+            }),
+        );
+        let client = fake_postgrest(router).await;
+        let ids = Collection::registered_schema_ids_batch(
+            &client,
+            "acmeCo/things",
+            &schemas.iter().collect::<Vec<_>>(),
+            CanonicalFormPolicy::Strict,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(ids, vec![5, 6]);
+        assert_eq!(inserts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_schema_registration_audit() {
+        use axum::{extract::Query, routing::get, Json};
+
+        #[derive(Debug, Default)]
+        struct CapturingSink(Mutex<Vec<SchemaRegistration>>);
+
+        impl SchemaAuditSink for CapturingSink {
+            fn record_registration(&self, registration: SchemaRegistration) {
+                self.0.lock().unwrap().push(registration);
+            }
+        }
+
+        let collection = collection_fixture(spec_fixture());
+        let (_, key_md5) =
+            content_address(&collection.key_schema, CanonicalFormPolicy::Strict).unwrap();
+
+        // A fake PostgREST which registers a schema on its first insert.
+        let registered = Arc::new(Mutex::new(false));
+        let (select_registered, insert_registered) = (registered.clone(), registered.clone());
+
+        let router = axum::Router::new().route(
+            "/registered_avro_schemas",
+            get(move |Query(_query): Query<BTreeMap<String, String>>| {
+                let rows = if *select_registered.lock().unwrap() {
+                    json!([{"registry_id": 21}])
+                } else {
+                    json!([])
+                };
+                async move { Json(rows) }
+            })
+            .post(move || {
+                *insert_registered.lock().unwrap() = true;
+                async { Json(json!([{"registry_id": 21}])) }
+            }),
+        );
+        let client = fake_postgrest(router).await;
+        let sink = Arc::new(CapturingSink::default());
+        let options = Options {
+            audit_sink: Some(sink.clone()),
+            audit_registrant: Some("refresh-token-1234".to_string()),
+            ..Default::default()
+        };
+
+        // The first registration inserts, and the second finds the registered schema.
+        for _ in 0..2 {
+            let id = Collection::registered_schema_id(
+                &client,
+                &collection.spec.name,
+                &collection.registry_schemas.0,
+                telemetry::SchemaKind::Key,
+                &options,
+            )
+            .await
+            .unwrap();
+            assert_eq!(id.id, 21);
+        }
+
+        let records = sink.0.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0],
+            SchemaRegistration {
+                registrant: Some("refresh-token-1234".to_string()),
+                registered_at: records[0].registered_at,
+                subject: "acmeCo/things".to_string(),
+                schema_md5: key_md5,
+                registry_id: 21,
+            }
+        );
+    }
+}
@@ -14,20 +14,38 @@ type SubClient = proto_grpc::broker::journal_client::JournalClient<
 
 #[derive(Clone)]
 pub struct Client {
+    fragment_reads: FragmentReadOptions,
     http: reqwest::Client,
     metadata: crate::Metadata,
     router: crate::Router,
 }
 
+/// FragmentReadOptions tune direct reads of fragments from cloud storage.
+#[derive(Clone, Debug, Default)]
+pub struct FragmentReadOptions {
+    /// Timeout of each HTTP request of a fragment read.
+    pub timeout: Option<std::time::Duration>,
+    /// If set, fragments are read through a sequence of HTTP range requests
+    /// of at most this many bytes, rather than a single request.
+    pub chunk_size: Option<u64>,
+}
+
 impl Client {
     pub fn new(http: reqwest::Client, router: crate::Router, metadata: crate::Metadata) -> Self {
         Self {
+            fragment_reads: FragmentReadOptions::default(),
             metadata,
             http,
             router,
         }
     }
 
+    /// Use the given FragmentReadOptions for direct reads of fragments.
+    pub fn with_fragment_reads(mut self, fragment_reads: FragmentReadOptions) -> Self {
+        self.fragment_reads = fragment_reads;
+        self
+    }
+
     pub async fn apply(&self, req: broker::ApplyRequest) -> crate::Result<broker::ApplyResponse> {
         let mut client = self.into_sub(self.router.route(None, false).await?);
 
@@ -1,6 +1,6 @@
-use super::Client;
+use super::{Client, FragmentReadOptions};
 use crate::Error;
-use futures::TryStreamExt;
+use futures::{StreamExt, TryStreamExt};
 use proto_gazette::broker;
 
 impl Client {
@@ -39,6 +39,33 @@ impl Client {
         })
     }
 
+    /// Fetch the current write head of `journal`, which is the offset at which
+    /// its next append will be written. Unlike the end of its newest fragment,
+    /// the write head reflects content which brokers haven't yet persisted.
+    pub async fn write_head(&self, journal: &str) -> crate::Result<i64> {
+        let mut client = self.into_sub(self.router.route(None, false).await?);
+
+        // A non-blocking metadata read of the journal head resolves the write
+        // head without reading content, and is "not yet available" if the
+        // head isn't covered by a fragment.
+        let mut stream = client
+            .read(broker::ReadRequest {
+                journal: journal.to_string(),
+                offset: -1,
+                block: false,
+                metadata_only: true,
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+        let metadata = stream.try_next().await?.ok_or(Error::UnexpectedEof)?;
+
+        match metadata.status() {
+            broker::Status::Ok | broker::Status::OffsetNotYetAvailable => Ok(metadata.write_head),
+            status => Err(Error::BrokerStatus(status)),
+        }
+    }
+
     async fn read_some(
         &self,
         co: &mut coroutines::Suspend<crate::Result<broker::ReadResponse>, ()>,
@@ -71,7 +98,15 @@ impl Client {
             *write_head = metadata.write_head;
             let (fragment, fragment_url) = (fragment.clone(), metadata.fragment_url.clone());
             () = co.yield_(Ok(metadata)).await;
-            return read_fragment_url(co, fragment, fragment_url, &self.http, req).await;
+            return read_fragment_url(
+                co,
+                fragment,
+                fragment_url,
+                &self.http,
+                &self.fragment_reads,
+                req,
+            )
+            .await;
         }
 
         tracing::trace!(req.offset, write_head, "started direct journal read");
@@ -115,29 +150,49 @@ async fn read_fragment_url(
     fragment: broker::Fragment,
     fragment_url: String,
     http: &reqwest::Client,
+    options: &FragmentReadOptions,
     req: &mut broker::ReadRequest,
 ) -> crate::Result<()> {
-    let mut get = http.get(fragment_url);
+    let codec = fragment.compression_codec();
+    let (http, timeout) = (http.clone(), options.timeout);
 
-    match fragment.compression_codec() {
-        broker::CompressionCodec::GzipOffloadDecompression => {
-            get = get.header("Accept-Encoding", "identity");
+    let get = move || {
+        let mut get = http.get(&fragment_url);
+
+        match codec {
+            broker::CompressionCodec::GzipOffloadDecompression => {
+                get = get.header("Accept-Encoding", "identity");
+            }
+            broker::CompressionCodec::Gzip => {
+                get = get.header("Accept-Encoding", "gzip");
+            }
+            _ => {}
         }
-        broker::CompressionCodec::Gzip => {
-            get = get.header("Accept-Encoding", "gzip");
+        if let Some(timeout) = timeout {
+            get = get.timeout(timeout);
         }
-        _ => {}
-    }
+        get
+    };
+
+    let body = match options.chunk_size {
+        // Range requests of an offloaded decompression apply to the decompressed
+        // content, which storage servers generally don't support. Read it whole.
+        Some(chunk_size) if codec != broker::CompressionCodec::GzipOffloadDecompression => {
+            read_ranges(get, chunk_size.max(1)).boxed()
+        }
+        _ => {
+            let response = get()
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(Error::FetchFragment)?;
 
-    let response = get
-        .send()
-        .await
-        .and_then(reqwest::Response::error_for_status)
-        .map_err(Error::FetchFragment)?;
+            // Map into a Stream<Item = Result<Bytes, _>>.
+            response.bytes_stream().boxed()
+        }
+    };
 
-    let raw_reader = response
-        // Map into a Stream<Item = Result<Bytes, _>>.
-        .bytes_stream()
+    let raw_reader = body
         // Wrap reqwest::Error as an io::Error for compatibility with AsyncBufRead.
         .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
         // Adapt into an AsyncBufRead.
@@ -164,6 +219,61 @@ async fn read_fragment_url(
     }
 }
 
+/// Read the object of `get` as a sequence of HTTP range requests of `chunk_size` bytes,
+/// concatenating their bodies. Reads stop upon a response which reaches the
+/// object's end, which isn't a partial response, or which isn't satisfiable.
+fn read_ranges<G>(
+    get: G,
+    chunk_size: u64,
+) -> impl futures::Stream<Item = reqwest::Result<bytes::Bytes>> + Send + 'static
+where
+    G: Fn() -> reqwest::RequestBuilder + Send + 'static,
+{
+    futures::stream::try_unfold((get, Some(0)), move |(get, begin)| async move {
+        let Some(begin) = begin else {
+            return Ok(None); // Prior range reached the end of the object.
+        };
+        let response = get()
+            .header("Range", range_header(begin, chunk_size))
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(None); // `begin` is at or beyond the end of the object.
+        }
+        let response = response.error_for_status()?;
+
+        let size = response
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(content_range_size);
+
+        let next = match (response.status(), size) {
+            (reqwest::StatusCode::PARTIAL_CONTENT, Some(size)) if begin + chunk_size < size => {
+                Some(begin + chunk_size)
+            }
+            (reqwest::StatusCode::PARTIAL_CONTENT, None) => Some(begin + chunk_size),
+            // The object is complete, or the server ignored our Range and sent it whole.
+            _ => None,
+        };
+
+        Ok(Some((response.bytes_stream(), (get, next))))
+    })
+    .try_flatten()
+}
+
+/// Build the HTTP Range header of the chunk of `chunk_size` bytes beginning at `begin`.
+fn range_header(begin: u64, chunk_size: u64) -> String {
+    format!("bytes={begin}-{}", begin + chunk_size - 1)
+}
+
+/// Parse the complete object size of a Content-Range header, like "bytes 0-99/1234".
+fn content_range_size(value: &str) -> Option<u64> {
+    let (_, size) = value.rsplit_once('/')?;
+    size.parse().ok()
+}
+
 async fn read_fragment_url_body(
     co: &mut coroutines::Suspend<crate::Result<broker::ReadResponse>, ()>,
     fragment: broker::Fragment,
@@ -222,3 +332,19 @@ async fn read_fragment_url_body(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fragment_range_boundaries() {
+        assert_eq!(range_header(0, 1024), "bytes=0-1023");
+        assert_eq!(range_header(1024, 1024), "bytes=1024-2047");
+        assert_eq!(range_header(5, 1), "bytes=5-5");
+
+        assert_eq!(content_range_size("bytes 0-1023/4000"), Some(4000));
+        assert_eq!(content_range_size("bytes 0-1023/*"), None);
+        assert_eq!(content_range_size("garbage"), None);
+    }
+}
@@ -41,6 +41,12 @@ pub enum Error {
     UnexpectedEof,
     #[error("JWT error")]
     JWT(#[from] jsonwebtoken::errors::Error),
+    #[error("failed to dial all {count} candidate endpoints")]
+    EndpointsExhausted {
+        count: usize,
+        #[source]
+        last: Box<Error>,
+    },
 }
 
 impl Error {
@@ -51,6 +57,8 @@ impl Error {
             Error::FetchFragment(_) => true,
             Error::ReadFragment(_) => true,
             Error::UnexpectedEof => true,
+            // Unavailable endpoints may become available again.
+            Error::EndpointsExhausted { .. } => true,
 
             // Some gRPC codes are transient failures.
             Error::Grpc(status) => match status.code() {
@@ -80,18 +88,30 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Dial a gRPC endpoint with opinionated defaults and
 /// support for TLS and Unix Domain Sockets.
 pub async fn dial_channel(endpoint: &str) -> Result<tonic::transport::Channel> {
+    dial_channel_with_identity(endpoint, None).await
+}
+
+/// Dial a Channel as does `dial_channel`, presenting the client certificate
+/// `identity` (if any) for mutual TLS with the endpoint or a proxy before it.
+pub async fn dial_channel_with_identity(
+    endpoint: &str,
+    identity: Option<&tonic::transport::Identity>,
+) -> Result<tonic::transport::Channel> {
     use std::time::Duration;
 
+    let mut tls = tonic::transport::ClientTlsConfig::new()
+        .with_native_roots()
+        .assume_http2(true);
+    if let Some(identity) = identity {
+        tls = tls.identity(identity.clone());
+    }
+
     let ep = tonic::transport::Endpoint::from_shared(endpoint.to_string())
         .map_err(|_err| Error::InvalidEndpoint(endpoint.to_string()))?
         .connect_timeout(Duration::from_secs(5))
         .keep_alive_timeout(Duration::from_secs(120))
         .keep_alive_while_idle(true)
-        .tls_config(
-            tonic::transport::ClientTlsConfig::new()
-                .with_native_roots()
-                .assume_http2(true),
-        )?;
+        .tls_config(tls)?;
 
     let channel = match ep.uri().scheme_str() {
         Some("unix") => {
@@ -21,28 +21,60 @@ pub struct Router {
 }
 struct Inner {
     states: std::sync::Mutex<HashMap<MemberId, DialState>>,
-    default_endpoint: String,
+    default_endpoints: Vec<String>,
     zone: String,
+    identity: Option<tonic::transport::Identity>,
 }
 
 impl Router {
     /// Create a new Router with the given default service endpoint,
     /// which prefers to route to members in `zone` where possible.
     pub fn new(default_endpoint: &str, zone: &str) -> Result<Self, Error> {
-        let (default_endpoint, zone) = (default_endpoint.to_string(), zone.to_string());
+        Self::new_with_identity(default_endpoint, zone, None)
+    }
+
+    /// Create a new Router as does `new`, which presents the client certificate
+    /// `identity` (if any) when dialing member and default endpoints.
+    pub fn new_with_identity(
+        default_endpoint: &str,
+        zone: &str,
+        identity: Option<tonic::transport::Identity>,
+    ) -> Result<Self, Error> {
+        Self::new_with_fallbacks(&[default_endpoint], zone, identity)
+    }
 
-        let _endpoint = tonic::transport::Endpoint::from_shared(default_endpoint.clone())
-            .map_err(|_err| Error::InvalidEndpoint(default_endpoint.clone()))?;
+    /// Create a new Router as does `new_with_identity`, having an ordered list of
+    /// candidate `default_endpoints`. When dialing the default endpoint, each
+    /// candidate is tried in turn until one connects, and the Router fails with
+    /// Error::EndpointsExhausted only if every candidate fails.
+    pub fn new_with_fallbacks(
+        default_endpoints: &[&str],
+        zone: &str,
+        identity: Option<tonic::transport::Identity>,
+    ) -> Result<Self, Error> {
+        if default_endpoints.is_empty() {
+            return Err(Error::InvalidEndpoint(String::new()));
+        }
+        for endpoint in default_endpoints {
+            let _endpoint = tonic::transport::Endpoint::from_shared(endpoint.to_string())
+                .map_err(|_err| Error::InvalidEndpoint(endpoint.to_string()))?;
+        }
 
         Ok(Self {
             inner: Arc::new(Inner {
                 states: Default::default(),
-                default_endpoint,
-                zone,
+                default_endpoints: default_endpoints.iter().map(|e| e.to_string()).collect(),
+                zone: zone.to_string(),
+                identity,
             }),
         })
     }
 
+    /// Zone of this Router, within which members are preferred.
+    pub fn zone(&self) -> &str {
+        &self.inner.zone
+    }
+
     /// Map an optional broker::Route and indication of whether the "primary"
     /// member is required into a ready Channel for use in the dispatch of an RPC.
     ///
@@ -65,17 +97,43 @@ impl Router {
         }
 
         // Slow path: start dialing the endpoint.
-        let channel = super::dial_channel(match index {
-            Some(index) => &route.unwrap().endpoints[index],
-            None => &self.inner.default_endpoint,
-        })
-        .await?;
+        let channel = match index {
+            Some(index) => {
+                super::dial_channel_with_identity(
+                    &route.unwrap().endpoints[index],
+                    self.inner.identity.as_ref(),
+                )
+                .await?
+            }
+            None => self.dial_default().await?,
+        };
 
         *state = Some((channel.clone(), 1));
 
         Ok(channel)
     }
 
+    // Dial the first of the default endpoints which connects.
+    async fn dial_default(&self) -> Result<Channel, Error> {
+        let endpoints = &self.inner.default_endpoints;
+        let mut last_err = None;
+
+        for endpoint in endpoints {
+            match super::dial_channel_with_identity(endpoint, self.inner.identity.as_ref()).await {
+                Ok(channel) => return Ok(channel),
+                Err(err) if endpoints.len() == 1 => return Err(err),
+                Err(err) => {
+                    tracing::warn!(%endpoint, %err, "failed to dial default endpoint; trying the next");
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(Error::EndpointsExhausted {
+            count: endpoints.len(),
+            last: Box::new(last_err.expect("default endpoints are not empty")),
+        })
+    }
+
     fn pick(&self, route: Option<&broker::Route>, primary: bool) -> (Option<usize>, DialState) {
         // Acquire non-async lock which *cannot* be held across an await point.
         let mut states = self.inner.states.lock().unwrap();
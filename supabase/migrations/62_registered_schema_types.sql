@@ -0,0 +1,17 @@
+begin;
+
+-- Schemas may be registered as Avro or JSON schemas, and are content-addressed
+-- within their type. The `avro_schema` column holds the schema of either type.
+alter table registered_avro_schemas
+  add column schema_type text not null default 'AVRO'
+  check (schema_type in ('AVRO', 'JSON'));
+
+comment on column registered_avro_schemas.schema_type is
+  'Type of the registered schema, as a Confluent Schema Registry schemaType';
+
+create index idx_registered_avro_schemas_schema_type_md5
+  on registered_avro_schemas (schema_type, avro_schema_md5);
+
+grant insert (catalog_name, avro_schema, schema_type) on registered_avro_schemas to authenticated;
+
+commit;